@@ -1,36 +1,333 @@
 use anyhow::{anyhow, Result};
 use aoc_runner_derive::{aoc, aoc_generator};
-use std::collections::HashSet;
 
 #[aoc_generator(day1)]
 pub fn input_generator(input: &str) -> Vec<i32> {
     input.lines().map(|l| l.parse().unwrap()).collect()
 }
 
-fn two_sum(input: &[i32], target: i32) -> Result<(i32, i32)> {
-    let mut hashset = HashSet::with_capacity(input.len());
-    for &i in input {
-        if hashset.contains(&i) {
-            return Ok((i, target - i));
+/// A set of distinct entries from the input, with indices in ascending order
+/// and `values[i] == input[indices[i]]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Combination {
+    pub indices: Vec<usize>,
+    pub values: Vec<i32>,
+}
+
+impl Combination {
+    fn from_indices(input: &[i32], mut indices: Vec<usize>) -> Self {
+        indices.sort_unstable();
+        let values = indices.iter().map(|&i| input[i]).collect();
+        Self { indices, values }
+    }
+
+    pub fn sum(&self) -> i64 {
+        self.values.iter().map(|&v| v as i64).sum()
+    }
+
+    pub fn product(&self) -> i64 {
+        self.values.iter().map(|&v| v as i64).product()
+    }
+}
+
+/// Finds every combination of `k` distinct entries summing to `target`.
+///
+/// Entries with equal values are treated as different entries, so `[5, 5, 5]`
+/// has three pairs summing to 10. Runs in O(n^(k-1)) after sorting, using a
+/// two pointer sweep for the innermost pair.
+pub fn k_sum(input: &[i32], k: usize, target: i32) -> Vec<Combination> {
+    let mut order: Vec<usize> = (0..input.len()).collect();
+    order.sort_by_key(|&i| input[i]);
+    let sorted: Vec<i64> = order.iter().map(|&i| input[i] as i64).collect();
+
+    let mut found = Vec::new();
+    let mut chosen = Vec::with_capacity(k);
+    k_sum_sorted(&sorted, 0, k, target as i64, &mut chosen, &mut found);
+
+    let mut out: Vec<Combination> = found
+        .into_iter()
+        .map(|c| Combination::from_indices(input, c.iter().map(|&i| order[i]).collect()))
+        .collect();
+    out.sort_unstable_by(|a, b| a.indices.cmp(&b.indices));
+    out
+}
+
+// Positions here index into `sorted`, and are mapped back to input indices by the caller
+fn k_sum_sorted(
+    sorted: &[i64],
+    start: usize,
+    k: usize,
+    target: i64,
+    chosen: &mut Vec<usize>,
+    found: &mut Vec<Vec<usize>>,
+) {
+    match k {
+        0 => {
+            if target == 0 {
+                found.push(chosen.clone());
+            }
+        }
+        1 => {
+            for (i, &v) in sorted.iter().enumerate().skip(start) {
+                if v == target {
+                    chosen.push(i);
+                    found.push(chosen.clone());
+                    chosen.pop();
+                }
+            }
+        }
+        2 => two_pointer(sorted, start, target, chosen, found),
+        _ => {
+            for i in start..sorted.len() {
+                chosen.push(i);
+                k_sum_sorted(sorted, i + 1, k - 1, target - sorted[i], chosen, found);
+                chosen.pop();
+            }
+        }
+    }
+}
+
+fn two_pointer(
+    sorted: &[i64],
+    start: usize,
+    target: i64,
+    chosen: &mut Vec<usize>,
+    found: &mut Vec<Vec<usize>>,
+) {
+    if sorted.len() < start + 2 {
+        return;
+    }
+    let mut lo = start;
+    let mut hi = sorted.len() - 1;
+    while lo < hi {
+        let sum = sorted[lo] + sorted[hi];
+        if sum < target {
+            lo += 1;
+        } else if sum > target {
+            hi -= 1;
+        } else if sorted[lo] == sorted[hi] {
+            // Every pair within the run of equal values matches
+            for a in lo..hi {
+                for b in a + 1..=hi {
+                    found.push([chosen.as_slice(), &[a, b]].concat());
+                }
+            }
+            break;
+        } else {
+            let lo_end = (lo..hi).find(|&i| sorted[i] != sorted[lo]).unwrap_or(hi);
+            let hi_start = (lo_end..=hi)
+                .rev()
+                .find(|&i| sorted[i] != sorted[hi])
+                .map_or(lo_end, |i| i + 1);
+            for a in lo..lo_end {
+                for b in hi_start..=hi {
+                    found.push([chosen.as_slice(), &[a, b]].concat());
+                }
+            }
+            lo = lo_end;
+            hi = hi_start - 1;
+        }
+    }
+}
+
+/// How to treat a search with no combination hitting the target exactly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SumMode {
+    /// Fail unless some combination sums to the target
+    Exact,
+    /// Fall back to the combination with the sum nearest the target
+    Closest,
+    /// Fall back to the nearest combination whose sum does not exceed the target
+    AtMost,
+}
+
+/// A best-effort match, where `difference` is the combination's sum minus the target.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NearestSum {
+    pub combination: Combination,
+    pub difference: i64,
+}
+
+/// Finds the combination of `k` distinct entries whose sum is nearest to
+/// `target`, optionally only considering sums at or below it.
+///
+/// On equal distances a sum below the target is preferred over one above it.
+pub fn closest_k_sum(input: &[i32], k: usize, target: i32, at_most: bool) -> Option<NearestSum> {
+    if k > input.len() {
+        return None;
+    }
+    let mut order: Vec<usize> = (0..input.len()).collect();
+    order.sort_by_key(|&i| input[i]);
+    let sorted: Vec<i64> = order.iter().map(|&i| input[i] as i64).collect();
+
+    let mut best = None;
+    let mut chosen = Vec::with_capacity(k);
+    closest_sorted(
+        &sorted,
+        0,
+        k,
+        target as i64,
+        at_most,
+        &mut chosen,
+        &mut best,
+    );
+
+    best.map(|(difference, c)| NearestSum {
+        combination: Combination::from_indices(input, c.iter().map(|&i| order[i]).collect()),
+        difference,
+    })
+}
+
+fn consider(
+    difference: i64,
+    at_most: bool,
+    chosen: &[usize],
+    extra: &[usize],
+    best: &mut Option<(i64, Vec<usize>)>,
+) {
+    if at_most && difference > 0 {
+        return;
+    }
+    let key = |d: i64| (d.abs(), d > 0);
+    if let Some(b) = best {
+        if key(b.0) <= key(difference) {
+            return;
         }
-        hashset.insert(target - i);
     }
-    Err(anyhow!("No solution found!"))
+    *best = Some((difference, [chosen, extra].concat()));
+}
+
+fn closest_sorted(
+    sorted: &[i64],
+    start: usize,
+    k: usize,
+    target: i64,
+    at_most: bool,
+    chosen: &mut Vec<usize>,
+    best: &mut Option<(i64, Vec<usize>)>,
+) {
+    match k {
+        0 => consider(-target, at_most, chosen, &[], best),
+        1 => {
+            for (i, &v) in sorted.iter().enumerate().skip(start) {
+                consider(v - target, at_most, chosen, &[i], best);
+            }
+        }
+        2 => {
+            if sorted.len() < start + 2 {
+                return;
+            }
+            let mut lo = start;
+            let mut hi = sorted.len() - 1;
+            while lo < hi {
+                let difference = sorted[lo] + sorted[hi] - target;
+                consider(difference, at_most, chosen, &[lo, hi], best);
+                match difference {
+                    d if d < 0 => lo += 1,
+                    d if d > 0 => hi -= 1,
+                    _ => return,
+                }
+            }
+        }
+        _ => {
+            for i in start..sorted.len() {
+                chosen.push(i);
+                closest_sorted(
+                    sorted,
+                    i + 1,
+                    k - 1,
+                    target - sorted[i],
+                    at_most,
+                    chosen,
+                    best,
+                );
+                chosen.pop();
+            }
+        }
+    }
+}
+
+/// Searches for `k` entries summing to `target`, falling back according to `mode`.
+pub fn find_sum(input: &[i32], k: usize, target: i32, mode: SumMode) -> Result<NearestSum> {
+    let found = match mode {
+        SumMode::Exact => k_sum(input, k, target)
+            .into_iter()
+            .next()
+            .map(|combination| NearestSum {
+                combination,
+                difference: 0,
+            }),
+        SumMode::Closest => closest_k_sum(input, k, target, false),
+        SumMode::AtMost => closest_k_sum(input, k, target, true),
+    };
+    found.ok_or_else(|| anyhow!("No solution found!"))
 }
 
 #[aoc(day1, part1)]
-pub fn solve_part1(input: &[i32]) -> Result<i32> {
-    let (a, b) = two_sum(input, 2020)?;
-    Ok(a * b)
+pub fn solve_part1(input: &[i32]) -> Result<i64> {
+    Ok(find_sum(input, 2, 2020, SumMode::Exact)?
+        .combination
+        .product())
 }
 
 #[aoc(day1, part2)]
-pub fn solve_part2(input: &[i32]) -> Result<i32> {
-    for (index, val) in input.iter().enumerate() {
-        let res = two_sum(&input[index + 1..], 2020 - val);
-        if let Ok(x) = res {
-            return Ok(x.0 * x.1 * val);
-        }
+pub fn solve_part2(input: &[i32]) -> Result<i64> {
+    Ok(find_sum(input, 3, 2020, SumMode::Exact)?
+        .combination
+        .product())
+}
+
+#[cfg(test)]
+mod tests {
+    // Note this useful idiom: importing names from outer (for mod tests) scope.
+    use super::*;
+
+    const EX: [i32; 6] = [1721, 979, 366, 299, 675, 1456];
+
+    #[test]
+    fn test1() -> Result<()> {
+        assert_eq!(solve_part1(&EX)?, 514579);
+        assert_eq!(solve_part2(&EX)?, 241861950);
+        Ok(())
+    }
+    #[test]
+    fn test_k_sum_duplicates() {
+        let sols = k_sum(&[5, 5, 5, 3, 7], 2, 10);
+        let indices: Vec<Vec<usize>> = sols.iter().map(|c| c.indices.clone()).collect();
+        assert_eq!(
+            indices,
+            vec![vec![0, 1], vec![0, 2], vec![1, 2], vec![3, 4]]
+        );
+        assert!(sols.iter().all(|c| c.sum() == 10));
+    }
+    #[test]
+    fn test_k_sum_general() {
+        let input = [1, 2, 3, 4, 5, -1, 0];
+        let sols = k_sum(&input, 4, 10);
+        assert_eq!(
+            sols.iter().map(|c| c.values.clone()).collect::<Vec<_>>(),
+            vec![
+                vec![1, 2, 3, 4],
+                vec![1, 4, 5, 0],
+                vec![2, 3, 5, 0],
+                vec![2, 4, 5, -1]
+            ]
+        );
+        assert_eq!(k_sum(&input, 1, 3)[0].indices, vec![2]);
+        assert!(k_sum(&input, 8, 0).is_empty());
+    }
+    #[test]
+    fn test_closest() -> Result<()> {
+        let input = [100, 250, 400, 1000];
+        let near = find_sum(&input, 2, 600, SumMode::Closest)?;
+        assert_eq!(near.combination.values, vec![250, 400]);
+        assert_eq!(near.difference, 50);
+        let below = find_sum(&input, 2, 600, SumMode::AtMost)?;
+        assert_eq!(below.combination.values, vec![100, 400]);
+        assert_eq!(below.difference, -100);
+        assert!(find_sum(&input, 2, 600, SumMode::Exact).is_err());
+        assert!(find_sum(&input, 3, 100, SumMode::AtMost).is_err());
+        assert_eq!(find_sum(&input, 3, 1350, SumMode::Closest)?.difference, 0);
+        Ok(())
     }
-    Err(anyhow!("No solution found!"))
 }
@@ -1,45 +1,464 @@
 use anyhow::{anyhow, Result};
 use aoc_runner_derive::{aoc, aoc_generator};
-use std::collections::HashMap;
+use std::fmt;
 use std::str::FromStr;
 
-#[derive(Debug)]
-pub struct Policy {
-    pub character: char,
-    pub min: u32,
-    pub max: u32,
+/// A password policy, parsed from either the legacy `min-max c` shorthand or
+/// a small rule language:
+///
+/// - `count c min-max`: `c` occurs between `min` and `max` times
+/// - `exactly k of c at p1,p2,...`: `c` is at exactly `k` of the 1-indexed positions
+/// - `forbid chars`: none of `chars` occur
+/// - `a and b`, `a or b` and parentheses, with `and` binding tighter
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Policy {
+    /// Legacy shorthand, read as a count range or as positions depending on the puzzle part
+    Range {
+        character: char,
+        min: u32,
+        max: u32,
+    },
+    Count {
+        character: char,
+        min: u32,
+        max: u32,
+    },
+    Positions {
+        character: char,
+        exactly: u32,
+        positions: Vec<u32>,
+    },
+    /// None of these characters may appear. Parentheses and whitespace have
+    /// no spelling in the policy language, so they are rejected when parsing.
+    Forbid(Vec<char>),
+    All(Vec<Policy>),
+    Any(Vec<Policy>),
+}
+
+impl Policy {
+    /// Reads legacy shorthand as a count range (part 1 rules).
+    pub fn as_count(&self) -> Policy {
+        match *self {
+            Policy::Range {
+                character,
+                min,
+                max,
+            } => Policy::Count {
+                character,
+                min,
+                max,
+            },
+            _ => self.clone(),
+        }
+    }
+
+    /// Reads legacy shorthand as "exactly one of two positions" (part 2 rules).
+    pub fn as_positions(&self) -> Policy {
+        match *self {
+            Policy::Range {
+                character,
+                min,
+                max,
+            } => Policy::Positions {
+                character,
+                exactly: 1,
+                positions: vec![min, max],
+            },
+            _ => self.clone(),
+        }
+    }
+
+    /// Checks a password against the policy, treating legacy shorthand as a count range.
+    pub fn is_valid(&self, s: &str) -> bool {
+        self.violations(s).is_empty()
+    }
+
+    /// Lists every way the password breaks the policy, empty if it passes.
+    pub fn violations(&self, s: &str) -> Vec<Violation> {
+        match self {
+            Policy::Range { .. } => self.as_count().violations(s),
+            Policy::Count {
+                character,
+                min,
+                max,
+            } => {
+                let count = s.chars().filter(|c| c == character).count() as u32;
+                if count < *min {
+                    vec![Violation::CountBelowMin {
+                        character: *character,
+                        count,
+                        min: *min,
+                    }]
+                } else if count > *max {
+                    vec![Violation::CountAboveMax {
+                        character: *character,
+                        count,
+                        max: *max,
+                    }]
+                } else {
+                    Vec::new()
+                }
+            }
+            Policy::Positions {
+                character,
+                exactly,
+                positions,
+            } => {
+                let chars: Vec<char> = s.chars().collect();
+                let mut out_of_range = Vec::new();
+                let mut matched = 0;
+                for &position in positions {
+                    // Positions are 1-indexed, any position outside the password fails it
+                    match chars.get((position as usize).wrapping_sub(1)) {
+                        Some(c) if c == character => matched += 1,
+                        Some(_) => {}
+                        None => out_of_range.push(Violation::PositionOutOfRange {
+                            position,
+                            len: chars.len(),
+                        }),
+                    }
+                }
+                if !out_of_range.is_empty() {
+                    out_of_range
+                } else if matched != *exactly {
+                    vec![Violation::PositionMatches {
+                        character: *character,
+                        matched,
+                        expected: *exactly,
+                        of: positions.len() as u32,
+                    }]
+                } else {
+                    Vec::new()
+                }
+            }
+            Policy::Forbid(forbidden) => s
+                .chars()
+                .enumerate()
+                .filter(|(_, c)| forbidden.contains(c))
+                .map(|(i, c)| Violation::Forbidden {
+                    character: c,
+                    position: i as u32 + 1,
+                })
+                .collect(),
+            Policy::All(policies) => policies.iter().flat_map(|p| p.violations(s)).collect(),
+            Policy::Any(policies) => {
+                let mut alternatives = Vec::with_capacity(policies.len());
+                for p in policies {
+                    let v = p.violations(s);
+                    if v.is_empty() {
+                        return v;
+                    }
+                    alternatives.push(v);
+                }
+                vec![Violation::NoAlternative(alternatives)]
+            }
+        }
+    }
+}
+
+/// Why a password failed a policy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    CountBelowMin {
+        character: char,
+        count: u32,
+        min: u32,
+    },
+    CountAboveMax {
+        character: char,
+        count: u32,
+        max: u32,
+    },
+    PositionOutOfRange {
+        position: u32,
+        len: usize,
+    },
+    PositionMatches {
+        character: char,
+        matched: u32,
+        expected: u32,
+        of: u32,
+    },
+    Forbidden {
+        character: char,
+        position: u32,
+    },
+    /// Every branch of an `or` failed, with the violations of each branch
+    NoAlternative(Vec<Vec<Violation>>),
+}
+
+fn join_violations(violations: &[Violation], sep: &str) -> String {
+    violations
+        .iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<String>>()
+        .join(sep)
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::CountBelowMin { count, min, .. } => {
+                write!(f, "count {} below min {}", count, min)
+            }
+            Violation::CountAboveMax { count, max, .. } => {
+                write!(f, "count {} exceeds max {}", count, max)
+            }
+            Violation::PositionOutOfRange { position, .. } => {
+                write!(f, "position {} out of range", position)
+            }
+            Violation::PositionMatches {
+                matched: 2,
+                expected: 1,
+                of: 2,
+                ..
+            } => write!(f, "both positions match"),
+            Violation::PositionMatches {
+                matched: 0,
+                expected,
+                ..
+            } => write!(f, "no positions match, expected {}", expected),
+            Violation::PositionMatches {
+                matched,
+                expected,
+                of,
+                ..
+            } => write!(
+                f,
+                "{} of {} positions match, expected {}",
+                matched, of, expected
+            ),
+            Violation::Forbidden {
+                character,
+                position,
+            } => write!(f, "forbidden {} at position {}", character, position),
+            Violation::NoAlternative(alternatives) => {
+                let branches: Vec<String> = alternatives
+                    .iter()
+                    .map(|v| join_violations(v, ", "))
+                    .collect();
+                write!(f, "no alternative passes ({})", branches.join(" | "))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Policy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let join = |f: &mut fmt::Formatter<'_>, policies: &[Policy], sep: &str| {
+            for (i, p) in policies.iter().enumerate() {
+                if i > 0 {
+                    write!(f, " {} ", sep)?;
+                }
+                match p {
+                    Policy::All(_) | Policy::Any(_) => write!(f, "({})", p)?,
+                    _ => write!(f, "{}", p)?,
+                }
+            }
+            Ok(())
+        };
+        match self {
+            Policy::Range {
+                character,
+                min,
+                max,
+            } => write!(f, "{}-{} {}", min, max, character),
+            Policy::Count {
+                character,
+                min,
+                max,
+            } => write!(f, "count {} {}-{}", character, min, max),
+            Policy::Positions {
+                character,
+                exactly,
+                positions,
+            } => {
+                let positions: Vec<String> = positions.iter().map(|p| p.to_string()).collect();
+                write!(
+                    f,
+                    "exactly {} of {} at {}",
+                    exactly,
+                    character,
+                    positions.join(",")
+                )
+            }
+            Policy::Forbid(chars) => write!(f, "forbid {}", chars.iter().collect::<String>()),
+            Policy::All(policies) => join(f, policies, "and"),
+            Policy::Any(policies) => join(f, policies, "or"),
+        }
+    }
+}
+
+fn tokenize(s: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut cur = String::new();
+    for c in s.chars() {
+        if c.is_whitespace() || c == '(' || c == ')' {
+            if !cur.is_empty() {
+                tokens.push(std::mem::take(&mut cur));
+            }
+            if !c.is_whitespace() {
+                tokens.push(c.to_string());
+            }
+        } else {
+            cur.push(c);
+        }
+    }
+    if !cur.is_empty() {
+        tokens.push(cur);
+    }
+    tokens
+}
+
+fn parse_range(s: &str) -> Result<(u32, u32)> {
+    let mut range_split = s.split('-');
+    let min: u32 = range_split
+        .next()
+        .ok_or_else(|| anyhow!("No - in range"))?
+        .parse()?;
+    let max: u32 = range_split
+        .next()
+        .ok_or_else(|| anyhow!("No - in range"))?
+        .parse()?;
+    if range_split.next().is_some() {
+        return Err(anyhow!("Bad range: {}", s));
+    }
+    Ok((min, max))
+}
+
+fn parse_char(s: &str) -> Result<char> {
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c != '(' && c != ')' => Ok(c),
+        _ => Err(anyhow!("Expected a single character, got: {}", s)),
+    }
+}
+
+struct PolicyParser {
+    tokens: Vec<String>,
+    pos: usize,
+}
+
+impl PolicyParser {
+    fn next(&mut self) -> Result<&str> {
+        let tok = self
+            .tokens
+            .get(self.pos)
+            .ok_or_else(|| anyhow!("Unexpected end of policy"))?;
+        self.pos += 1;
+        Ok(tok)
+    }
+
+    fn eat(&mut self, keyword: &str) -> bool {
+        let found = self.tokens.get(self.pos).map(|t| t.as_str()) == Some(keyword);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, keyword: &str) -> Result<()> {
+        if self.eat(keyword) {
+            Ok(())
+        } else {
+            Err(anyhow!("Expected {} at token {}", keyword, self.pos))
+        }
+    }
+
+    fn parse_any(&mut self) -> Result<Policy> {
+        let mut policies = vec![self.parse_all()?];
+        while self.eat("or") {
+            policies.push(self.parse_all()?);
+        }
+        Ok(if policies.len() == 1 {
+            policies.remove(0)
+        } else {
+            Policy::Any(policies)
+        })
+    }
+
+    fn parse_all(&mut self) -> Result<Policy> {
+        let mut policies = vec![self.parse_atom()?];
+        while self.eat("and") {
+            policies.push(self.parse_atom()?);
+        }
+        Ok(if policies.len() == 1 {
+            policies.remove(0)
+        } else {
+            Policy::All(policies)
+        })
+    }
+
+    fn parse_atom(&mut self) -> Result<Policy> {
+        match self.next()? {
+            "(" => {
+                let policy = self.parse_any()?;
+                self.expect(")")?;
+                Ok(policy)
+            }
+            "count" => {
+                let character = parse_char(self.next()?)?;
+                let (min, max) = parse_range(self.next()?)?;
+                Ok(Policy::Count {
+                    character,
+                    min,
+                    max,
+                })
+            }
+            "exactly" => {
+                let exactly = self.next()?.parse()?;
+                self.expect("of")?;
+                let character = parse_char(self.next()?)?;
+                self.expect("at")?;
+                let positions = self
+                    .next()?
+                    .split(',')
+                    .map(|p| p.parse())
+                    .collect::<Result<Vec<u32>, _>>()?;
+                Ok(Policy::Positions {
+                    character,
+                    exactly,
+                    positions,
+                })
+            }
+            "forbid" => match self.next()? {
+                tok @ ("(" | ")") => Err(anyhow!("Expected characters to forbid, got {}", tok)),
+                tok => Ok(Policy::Forbid(tok.chars().collect())),
+            },
+            tok => Err(anyhow!("Unknown policy term: {}", tok)),
+        }
+    }
 }
 
 impl FromStr for Policy {
     type Err = anyhow::Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut siter = s.split(' ');
-        let range_str = siter
-            .next()
-            .ok_or_else(|| anyhow!("No space in policy string"))?;
-        let mut range_split = range_str.split('-');
-        let min: u32 = range_split
-            .next()
-            .ok_or_else(|| anyhow!("No - in range"))?
-            .parse()?;
-        let max: u32 = range_split
-            .next()
-            .ok_or_else(|| anyhow!("No - in range"))?
-            .parse()?;
-        let character = siter
-            .next()
-            .ok_or_else(|| anyhow!("No char"))?
-            .chars()
-            .next()
-            .ok_or_else(|| anyhow!("No char"))?;
-        // let s = siter.next().ok_or_else(|| anyhow!("No string part"))?.trim().to_string();
-
-        Ok(Self {
-            character,
-            min,
-            max,
-        })
+        let tokens = tokenize(s);
+        if tokens.is_empty() {
+            return Err(anyhow!("Empty policy"));
+        }
+
+        // Legacy shorthand: "1-3 a"
+        if tokens.len() == 2 && tokens[0].starts_with(|c: char| c.is_ascii_digit()) {
+            let (min, max) = parse_range(&tokens[0])?;
+            let character = parse_char(&tokens[1])?;
+            return Ok(Policy::Range {
+                character,
+                min,
+                max,
+            });
+        }
+
+        let mut parser = PolicyParser { tokens, pos: 0 };
+        let policy = parser.parse_any()?;
+        if parser.pos != parser.tokens.len() {
+            return Err(anyhow!(
+                "Trailing tokens in policy: {}",
+                parser.tokens[parser.pos..].join(" ")
+            ));
+        }
+        Ok(policy)
     }
 }
 
@@ -62,52 +481,183 @@ pub fn input_generator(input: &str) -> Result<Vec<(Policy, String)>> {
     input.lines().map(|l| split_input(l)).collect()
 }
 
-fn get_char_count(s: &str) -> HashMap<char, u32> {
-    s.chars().fold(HashMap::with_capacity(26), |mut acc, x| {
-        acc.entry(x).and_modify(|i| *i += 1).or_insert(1);
-        acc
-    })
+#[aoc(day2, part1)]
+pub fn solve_part1(input: &[(Policy, String)]) -> usize {
+    input
+        .iter()
+        .filter(|(policy, s)| policy.as_count().is_valid(s))
+        .count()
 }
 
-#[aoc(day2, part1)]
-pub fn solve_part1(input: &[(Policy, String)]) -> u32 {
-    input.iter().fold(0, |acc, x| {
-        let hm = get_char_count(&x.1);
-        if let Some(y) = hm.get_key_value(&x.0.character) {
-            if y.1 >= &x.0.min && y.1 <= &x.0.max {
-                return acc + 1;
-            }
+fn part2_valid(s: &str, policy: &Policy) -> bool {
+    policy.as_positions().is_valid(s)
+}
+
+#[aoc(day2, part2)]
+pub fn solve_part2(input: &[(Policy, String)]) -> usize {
+    input
+        .iter()
+        .filter(|(policy, s)| part2_valid(s, policy))
+        .count()
+}
+
+/// Which puzzle rules to read legacy `min-max c` policies with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interpretation {
+    Count,
+    Positions,
+}
+
+impl Interpretation {
+    pub fn apply(self, policy: &Policy) -> Policy {
+        match self {
+            Interpretation::Count => policy.as_count(),
+            Interpretation::Positions => policy.as_positions(),
         }
-        acc
-    })
+    }
 }
 
-fn part2_valid(s: &str, policy: &Policy) -> bool {
-    let mut chars_iter = s.chars();
-    let first_char = chars_iter.nth((policy.min - 1) as usize);
-    let second_char = chars_iter.nth((policy.max - policy.min - 1) as usize);
-    if let Some(fc) = first_char {
-        if let Some(sc) = second_char {
-            if (fc == policy.character && sc != policy.character)
-                || (sc == policy.character && fc != policy.character)
-            {
-                // println!("{:?}, {:?}, {:?}, {:?}", s, policy, fc, sc);
-                return true;
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditEntry {
+    /// 1-indexed line number in the input
+    pub line: usize,
+    pub policy: Policy,
+    pub password: String,
+    pub violations: Vec<Violation>,
+}
+
+impl AuditEntry {
+    pub fn passed(&self) -> bool {
+        self.violations.is_empty()
+    }
+}
+
+/// Checks every line, keeping the reasons for each failure.
+pub fn audit(input: &[(Policy, String)], interpretation: Interpretation) -> Vec<AuditEntry> {
+    input
+        .iter()
+        .enumerate()
+        .map(|(i, (policy, password))| {
+            let policy = interpretation.apply(policy);
+            AuditEntry {
+                line: i + 1,
+                violations: policy.violations(password),
+                policy,
+                password: password.clone(),
             }
-        }
+        })
+        .collect()
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
     }
-    false
 }
 
-#[aoc(day2, part2)]
-pub fn solve_part2(input: &[(Policy, String)]) -> u32 {
-    input.iter().fold(0, |acc, x| {
-        if part2_valid(x.1.as_str(), &x.0) {
-            acc + 1
-        } else {
-            acc
+/// Renders an audit as CSV with one row per line and `; `-separated reasons.
+pub fn audit_csv(entries: &[AuditEntry]) -> String {
+    let mut out = String::from("line,passed,policy,password,reasons\n");
+    for e in entries {
+        out.push_str(&format!(
+            "{},{},{},{},{}\n",
+            e.line,
+            e.passed(),
+            csv_field(&e.policy.to_string()),
+            csv_field(&e.password),
+            csv_field(&join_violations(&e.violations, "; "))
+        ));
+    }
+    out
+}
+
+/// Finds the legacy `min-max c` policies consistent with passwords already
+/// labelled valid or invalid, most specific first.
+///
+/// Under the count interpretation each character gets the tightest range
+/// covering its valid passwords, ordered by range width. Characters absent
+/// from every labelled password are not tried, as they could only explain
+/// a set with no invalid passwords. Under the positional
+/// interpretation every consistent position pair is returned. At least one
+/// valid password is needed to pin down the policy.
+pub fn infer_policies(
+    labelled: &[(String, bool)],
+    interpretation: Interpretation,
+) -> Result<Vec<Policy>> {
+    let valid: Vec<&str> = labelled
+        .iter()
+        .filter(|(_, ok)| *ok)
+        .map(|(s, _)| s.as_str())
+        .collect();
+    if valid.is_empty() {
+        return Err(anyhow!("No valid passwords to infer a policy from"));
+    }
+
+    // Under the positional reading a valid password always contains the policy
+    // character. Under the count reading it may not when `min` is 0, but an
+    // invalid password must then contain it, so the labelled passwords
+    // between them hold every character worth trying.
+    let sources: Vec<&str> = match interpretation {
+        Interpretation::Count => labelled.iter().map(|(s, _)| s.as_str()).collect(),
+        Interpretation::Positions => valid.clone(),
+    };
+    let mut characters: Vec<char> = sources.iter().flat_map(|s| s.chars()).collect();
+    characters.sort_unstable();
+    characters.dedup();
+
+    let candidates: Vec<Policy> = match interpretation {
+        Interpretation::Count => characters
+            .into_iter()
+            .filter_map(|character| {
+                let counts = valid
+                    .iter()
+                    .map(|s| s.chars().filter(|&c| c == character).count() as u32);
+                let min = counts.clone().min()?;
+                let max = counts.max()?;
+                Some(Policy::Range {
+                    character,
+                    min,
+                    max,
+                })
+            })
+            .collect(),
+        Interpretation::Positions => {
+            let max_position = valid.iter().map(|s| s.chars().count()).min().unwrap_or(0) as u32;
+            characters
+                .into_iter()
+                .flat_map(|character| {
+                    (1..=max_position).flat_map(move |min| {
+                        (min + 1..=max_position).map(move |max| Policy::Range {
+                            character,
+                            min,
+                            max,
+                        })
+                    })
+                })
+                .collect()
         }
-    })
+    };
+
+    let mut consistent: Vec<Policy> = candidates
+        .into_iter()
+        .filter(|policy| {
+            let rule = interpretation.apply(policy);
+            labelled.iter().all(|(s, ok)| rule.is_valid(s) == *ok)
+        })
+        .collect();
+    if consistent.is_empty() {
+        return Err(anyhow!(
+            "No {:?} policy explains the labelled passwords",
+            interpretation
+        ));
+    }
+    consistent.sort_by_key(|policy| match policy {
+        Policy::Range { max, min, .. } if interpretation == Interpretation::Count => max - min,
+        _ => 0,
+    });
+    Ok(consistent)
 }
 
 #[cfg(test)]
@@ -136,4 +686,101 @@ mod tests {
         assert!(!part2_valid(&s, &policy));
         Ok(())
     }
+    #[test]
+    fn test_policy_language() -> Result<()> {
+        let policy = Policy::from_str("count a 1-3 and (exactly 1 of b at 1,2,3 or forbid xyz)")?;
+        assert!(policy.is_valid("bcaa"));
+        assert!(policy.is_valid("ccaa"));
+        assert!(!policy.is_valid("bbax"));
+        assert!(!policy.is_valid("ccbb"));
+        assert_eq!(Policy::from_str(&policy.to_string())?, policy);
+        assert!(Policy::from_str("count a 1-3 and").is_err());
+        assert!(Policy::from_str("count ab 1-3").is_err());
+        assert!(Policy::from_str("count ( 1-3").is_err());
+        assert!(Policy::from_str("1-3 )").is_err());
+        assert!(Policy::from_str("forbid (x").is_err());
+        assert!(Policy::from_str("forbid ()").is_err());
+        assert!(Policy::from_str("(forbid )").is_err());
+        Ok(())
+    }
+    #[test]
+    fn test_legacy_policy() -> Result<()> {
+        let input = input_generator("1-3 a: abcde\n1-3 b: cdefg\n2-9 c: ccccccccc")?;
+        assert_eq!(solve_part1(&input), 2);
+        assert_eq!(solve_part2(&input), 1);
+        Ok(())
+    }
+    #[test]
+    fn test_audit() -> Result<()> {
+        let input = input_generator("1-3 a: aaaaaaa\n1-9 b: bab\n1-3 c: cbc\n2-2 d: ddd")?;
+        let counted = audit(&input, Interpretation::Count);
+        assert_eq!(
+            counted[0].violations[0].to_string(),
+            "count 7 exceeds max 3"
+        );
+        assert!(counted[1].passed());
+
+        let positional = audit(&input, Interpretation::Positions);
+        let reasons: Vec<String> = positional
+            .iter()
+            .map(|e| join_violations(&e.violations, "; "))
+            .collect();
+        assert_eq!(
+            reasons,
+            vec![
+                "both positions match",
+                "position 9 out of range",
+                "both positions match",
+                "both positions match"
+            ]
+        );
+        assert!(audit_csv(&positional)
+            .lines()
+            .any(|l| l == "2,false,\"exactly 1 of b at 1,9\",bab,position 9 out of range"));
+        Ok(())
+    }
+    #[test]
+    fn test_infer() -> Result<()> {
+        let labelled: Vec<(String, bool)> = vec![
+            ("abcde".to_string(), true),
+            ("cdefg".to_string(), false),
+            ("aaa".to_string(), true),
+            ("aaaaa".to_string(), false),
+        ];
+        let counted = infer_policies(&labelled, Interpretation::Count)?;
+        assert_eq!(
+            counted,
+            vec![Policy::Range {
+                character: 'a',
+                min: 1,
+                max: 3
+            }]
+        );
+
+        let labelled: Vec<(String, bool)> = vec![
+            ("abcde".to_string(), true),
+            ("cbade".to_string(), true),
+            ("cdefg".to_string(), false),
+            ("abade".to_string(), false),
+            ("xbxde".to_string(), false),
+        ];
+        let positional = infer_policies(&labelled, Interpretation::Positions)?;
+        assert_eq!(positional.len(), 1);
+        assert_eq!(positional[0].to_string(), "1-3 a");
+
+        // The character may be missing from every valid password
+        let absent = vec![("abc".to_string(), true), ("zzz".to_string(), false)];
+        let counted = infer_policies(&absent, Interpretation::Count)?;
+        assert_eq!(counted.len(), 4);
+        assert!(counted.contains(&Policy::Range {
+            character: 'z',
+            min: 0,
+            max: 0
+        }));
+
+        let contradictory = vec![("ab".to_string(), true), ("ab".to_string(), false)];
+        assert!(infer_policies(&contradictory, Interpretation::Count).is_err());
+        assert!(infer_policies(&labelled[2..3], Interpretation::Count).is_err());
+        Ok(())
+    }
 }
@@ -1,7 +1,10 @@
 use anyhow::{anyhow, Result};
 use aoc_runner_derive::{aoc, aoc_generator};
 
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap};
 use std::convert::TryFrom;
+use std::ops::RangeInclusive;
 
 #[derive(Debug)]
 pub enum Tile {
@@ -22,44 +25,380 @@ impl TryFrom<char> for Tile {
 
 #[aoc_generator(day3)]
 pub fn input_generator(input: &str) -> Result<Vec<Vec<Tile>>> {
-    input
+    let map = input
         .lines()
         .map(|l| {
             l.chars()
                 .map(|c| Tile::try_from(c))
                 .collect::<Result<Vec<Tile>, _>>()
         })
-        .collect()
+        .collect::<Result<Vec<_>>>()?;
+    map_width(&map)?;
+    Ok(map)
 }
 
-#[aoc(day3, part1)]
-pub fn solve_part1(map: &[Vec<Tile>]) -> i32 {
-    let slope = (3, 1);
-    common_solve(slope, map)
+// The width of every row, failing if the map is not rectangular
+fn map_width(map: &[Vec<Tile>]) -> Result<usize> {
+    let width = map.first().map_or(0, |r| r.len());
+    match map.iter().position(|r| r.len() != width) {
+        Some(y) => Err(anyhow!(
+            "Row {} has {} tiles, expected {}",
+            y + 1,
+            map[y].len(),
+            width
+        )),
+        None => Ok(width),
+    }
 }
 
-#[aoc(day3, part2)]
-pub fn solve_part2(map: &[Vec<Tile>]) -> i32 {
-    let slopes = [(1, 1), (3, 1), (5, 1), (7, 1), (1, 2)];
-    slopes.iter().fold(1, |acc, x| acc * common_solve(*x, map))
+/// How a slope is followed across the map.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sampling {
+    /// Jump `right` columns and `down` rows at once, only checking where each jump lands
+    Lattice,
+    /// Visit every row crossed by the line of gradient `right / down`, rounding
+    /// the column towards the left
+    Line,
+}
+
+/// A direction of travel. Negative `right` moves left and negative `down`
+/// starts from the bottom row and moves up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Slope {
+    pub right: i64,
+    pub down: i64,
+    pub sampling: Sampling,
+}
+
+impl Slope {
+    pub fn new(right: i64, down: i64) -> Self {
+        Self {
+            right,
+            down,
+            sampling: Sampling::Lattice,
+        }
+    }
+
+    /// A fractional slope of `right / down` columns per row, sampled on every row.
+    pub fn rational(right: i64, down: i64) -> Self {
+        Self {
+            right,
+            down,
+            sampling: Sampling::Line,
+        }
+    }
+
+    // Offset from the start after `step` moves, widened so that any `i64`
+    // slope can be stepped across a map without overflowing
+    fn offset(&self, step: i64) -> (i128, i128) {
+        let (step, right, down) = (step as i128, self.right as i128, self.down as i128);
+        match self.sampling {
+            Sampling::Lattice => (step * right, step * down),
+            Sampling::Line => ((step * right).div_euclid(down.abs()), step * down.signum()),
+        }
+    }
+}
+
+/// Where a traversal left the map.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exit {
+    Bottom,
+    Top,
+    /// Left the side of a non-wrapping map while heading for `row`
+    Left {
+        row: usize,
+    },
+    Right {
+        row: usize,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Traversal {
+    pub trees: usize,
+    /// Tiles checked, not counting the starting tile
+    pub visited: usize,
+    pub exit: Exit,
+}
+
+/// Follows `slope` from `start_column` on the top row (or the bottom row when
+/// moving up), counting trees. Without `wrap` the map is bounded and the
+/// traversal stops when it leaves either side.
+pub fn traverse(
+    map: &[Vec<Tile>],
+    slope: Slope,
+    start_column: usize,
+    wrap: bool,
+) -> Result<Traversal> {
+    if slope.down == 0 {
+        return Err(anyhow!("Slope must move vertically: {:?}", slope));
+    }
+    let height = map.len() as i128;
+    let width = map_width(map)? as i128;
+    if width == 0 {
+        return Err(anyhow!("Empty map"));
+    }
+    let start = (
+        start_column as i128,
+        if slope.down > 0 { 0 } else { height - 1 },
+    );
+
+    let mut trees = 0;
+    let mut visited = 0;
+    let exit = (1..)
+        .find_map(|step| {
+            let (dx, dy) = slope.offset(step);
+            let y = start.1 + dy;
+            if y >= height {
+                return Some(Exit::Bottom);
+            }
+            if y < 0 {
+                return Some(Exit::Top);
+            }
+            let x = start.0 + dx;
+            let x = if wrap {
+                x.rem_euclid(width)
+            } else if x < 0 {
+                return Some(Exit::Left { row: y as usize });
+            } else if x >= width {
+                return Some(Exit::Right { row: y as usize });
+            } else {
+                x
+            };
+
+            visited += 1;
+            if let Tile::Tree = map[y as usize][x as usize] {
+                trees += 1;
+            }
+            None
+        })
+        .expect("Unbounded range");
+
+    Ok(Traversal {
+        trees,
+        visited,
+        exit,
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SlopeScore {
+    pub slope: Slope,
+    pub trees: usize,
+    /// Euclidean distance travelled before leaving the map
+    pub length: f64,
+}
+
+/// Tree positions of each row packed into bitsets, so many slopes can be
+/// checked against the same map cheaply.
+struct TreeRows {
+    width: i64,
+    rows: Vec<Vec<u64>>,
+}
+
+impl TreeRows {
+    fn new(map: &[Vec<Tile>]) -> Self {
+        let width = map.first().map_or(0, |r| r.len());
+        let rows = map
+            .iter()
+            .map(|row| {
+                let mut bits = vec![0; width.div_ceil(64)];
+                for (x, tile) in row.iter().enumerate() {
+                    if let Tile::Tree = tile {
+                        bits[x / 64] |= 1 << (x % 64);
+                    }
+                }
+                bits
+            })
+            .collect();
+        Self {
+            width: width as i64,
+            rows,
+        }
+    }
+
+    fn is_tree(&self, x: i64, y: usize) -> bool {
+        let x = x.rem_euclid(self.width) as usize;
+        self.rows[y][x / 64] & (1 << (x % 64)) != 0
+    }
+}
+
+/// Evaluates every lattice slope with `right` and `down` in the given bounds
+/// on the wrapping map, starting from the top left (or bottom left when moving
+/// up). Results are ranked by fewest trees, then by shortest path.
+///
+/// Slopes whose `right` differ by a multiple of the map width hit the same
+/// tiles, so their tree counts are only computed once.
+pub fn rank_slopes(
+    map: &[Vec<Tile>],
+    right: RangeInclusive<i64>,
+    down: RangeInclusive<i64>,
+) -> Result<Vec<SlopeScore>> {
+    map_width(map)?;
+    let trees = TreeRows::new(map);
+    if trees.width == 0 {
+        return Err(anyhow!("Empty map"));
+    }
+    let height = map.len() as i64;
+
+    let mut cache: HashMap<(i64, i64), usize> = HashMap::new();
+    let mut scores = Vec::new();
+    for d in down.filter(|&d| d != 0) {
+        let steps = (height - 1) / d.abs();
+        let start_row = if d > 0 { 0 } else { height - 1 };
+        for r in right.clone() {
+            let reduced = r.rem_euclid(trees.width);
+            let count = *cache.entry((reduced, d)).or_insert_with(|| {
+                (1..=steps)
+                    .filter(|&k| trees.is_tree(k * reduced, (start_row + k * d) as usize))
+                    .count()
+            });
+            scores.push(SlopeScore {
+                slope: Slope::new(r, d),
+                trees: count,
+                length: steps as f64 * (r as f64).hypot(d as f64),
+            });
+        }
+    }
+
+    scores.sort_by(|a, b| {
+        a.trees
+            .cmp(&b.trees)
+            .then(a.length.partial_cmp(&b.length).unwrap_or(Ordering::Equal))
+    });
+    Ok(scores)
+}
+
+/// The moves available to a pathfinder, as `(right, down)` offsets.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MoveSet(pub Vec<(i64, i64)>);
+
+impl MoveSet {
+    /// Down, left and right one tile at a time.
+    pub fn orthogonal() -> Self {
+        Self(vec![(0, 1), (-1, 0), (1, 0)])
+    }
+
+    /// The eight moves of a chess knight.
+    pub fn knight() -> Self {
+        Self(vec![
+            (1, 2),
+            (-1, 2),
+            (2, 1),
+            (-2, 1),
+            (1, -2),
+            (-1, -2),
+            (2, -1),
+            (-2, -1),
+        ])
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Search {
+    Dijkstra,
+    /// Dijkstra guided by the fewest moves still needed to reach the bottom row
+    AStar,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Route {
+    /// `(column, row)` of every tile visited, from the top row to the bottom row
+    pub path: Vec<(usize, usize)>,
+    /// Trees on the path, including the starting tile
+    pub trees: usize,
 }
 
-fn common_solve(slope: (usize, usize), map: &[Vec<Tile>]) -> i32 {
-    let width = map[0].len();
+/// Finds the route from any tile on the top row to any tile on the bottom row
+/// hitting the fewest trees, preferring fewer moves among equally good routes.
+/// Columns wrap around as in the puzzle. Returns `None` if the bottom row
+/// cannot be reached with the given moves, and fails on a non-rectangular map.
+pub fn find_route(map: &[Vec<Tile>], moves: &MoveSet, search: Search) -> Result<Option<Route>> {
     let height = map.len();
-    let mut pos = (0, 0);
-    let mut sum = 0;
-    while pos.1 < height {
-        pos = ((pos.0 + slope.0 as usize) % width, pos.1 + slope.1);
-        if pos.1 >= height {
-            break;
+    let width = map_width(map)?;
+    if width == 0 {
+        return Ok(None);
+    }
+    let index = |x: usize, y: usize| y * width + x;
+    let tree_cost = |x: usize, y: usize| match map[y][x] {
+        Tile::Tree => 1,
+        Tile::Open => 0,
+    };
+    let max_down = moves.0.iter().map(|m| m.1).max().unwrap_or(0);
+    let remaining_moves = |y: usize| match search {
+        Search::Dijkstra => 0,
+        Search::AStar if max_down > 0 => (height - 1 - y).div_ceil(max_down as usize),
+        Search::AStar => 0,
+    };
+
+    // Costs are compared as (trees, moves)
+    let mut best: Vec<Option<(usize, usize)>> = vec![None; width * height];
+    let mut prev: Vec<Option<usize>> = vec![None; width * height];
+    let mut queue = BinaryHeap::new();
+    for x in 0..width {
+        let cost = (tree_cost(x, 0), 0);
+        best[index(x, 0)] = Some(cost);
+        queue.push(Reverse(((cost.0, remaining_moves(0)), cost, (x, 0))));
+    }
+
+    while let Some(Reverse((_, cost, (x, y)))) = queue.pop() {
+        if best[index(x, y)] != Some(cost) {
+            continue;
+        }
+        if y == height - 1 {
+            let mut path = vec![(x, y)];
+            let mut cur = index(x, y);
+            while let Some(p) = prev[cur] {
+                path.push((p % width, p / width));
+                cur = p;
+            }
+            path.reverse();
+            return Ok(Some(Route {
+                path,
+                trees: cost.0,
+            }));
         }
 
-        if let Tile::Tree = map[pos.1][pos.0] {
-            sum += 1;
+        for &(dx, dy) in moves.0.iter() {
+            let ny = y as i64 + dy;
+            if ny < 0 || ny >= height as i64 {
+                continue;
+            }
+            let (nx, ny) = (
+                (x as i64 + dx).rem_euclid(width as i64) as usize,
+                ny as usize,
+            );
+            let next = (cost.0 + tree_cost(nx, ny), cost.1 + 1);
+            if best[index(nx, ny)].is_none_or(|b| next < b) {
+                best[index(nx, ny)] = Some(next);
+                prev[index(nx, ny)] = Some(index(x, y));
+                queue.push(Reverse((
+                    (next.0, next.1 + remaining_moves(ny)),
+                    next,
+                    (nx, ny),
+                )));
+            }
         }
     }
-    sum
+    Ok(None)
+}
+
+#[aoc(day3, part1)]
+pub fn solve_part1(map: &[Vec<Tile>]) -> Result<usize> {
+    let slope = (3, 1);
+    common_solve(slope, map)
+}
+
+#[aoc(day3, part2)]
+pub fn solve_part2(map: &[Vec<Tile>]) -> Result<usize> {
+    let slopes = [(1, 1), (3, 1), (5, 1), (7, 1), (1, 2)];
+    slopes
+        .iter()
+        .try_fold(1, |acc, x| Ok(acc * common_solve(*x, map)?))
+}
+
+fn common_solve(slope: (i64, i64), map: &[Vec<Tile>]) -> Result<usize> {
+    Ok(traverse(map, Slope::new(slope.0, slope.1), 0, true)?.trees)
 }
 
 #[cfg(test)]
@@ -81,7 +420,7 @@ mod tests {
 #...##....#
 .#..#...#.#";
         let input = input_generator(ex)?;
-        assert_eq!(solve_part1(&input), 7);
+        assert_eq!(solve_part1(&input)?, 7);
         Ok(())
     }
     #[test]
@@ -100,8 +439,125 @@ mod tests {
         let map = input_generator(ex)?;
 
         let slopes = [(1, 1), (3, 1), (5, 1), (7, 1), (1, 2)];
-        let vecsols: Vec<i32> = slopes.iter().map(|x| common_solve(*x, &map)).collect();
+        let vecsols: Vec<usize> = slopes
+            .iter()
+            .map(|x| common_solve(*x, &map))
+            .collect::<Result<_>>()?;
         assert_eq!(vecsols, vec![2, 7, 3, 4, 2]);
         Ok(())
     }
+    #[test]
+    fn test_slopes() -> Result<()> {
+        let ex = "#..
+.#.
+..#
+.#.";
+        let map = input_generator(ex)?;
+
+        let left = traverse(&map, Slope::new(-1, 1), 0, true)?;
+        assert_eq!((left.trees, left.visited, left.exit), (0, 3, Exit::Bottom));
+
+        let bounded = traverse(&map, Slope::new(-1, 1), 2, false)?;
+        assert_eq!((bounded.trees, bounded.visited), (1, 2));
+        assert_eq!(bounded.exit, Exit::Left { row: 3 });
+
+        let up = traverse(&map, Slope::new(1, -1), 0, false)?;
+        assert_eq!((up.trees, up.exit), (0, Exit::Right { row: 0 }));
+
+        // Half a column per row visits columns 0, 1, 1
+        let half = traverse(&map, Slope::rational(1, 2), 0, true)?;
+        assert_eq!((half.trees, half.visited), (1, 3));
+
+        assert!(traverse(&map, Slope::new(1, 0), 0, true).is_err());
+
+        // i64::MAX is 1 modulo the width of 3
+        let steep = traverse(&map, Slope::new(i64::MAX, 1), 0, true)?;
+        assert_eq!(steep, traverse(&map, Slope::new(1, 1), 0, true)?);
+        let steep = traverse(&map, Slope::rational(i64::MAX, 1), 0, true)?;
+        assert_eq!(steep, traverse(&map, Slope::new(1, 1), 0, true)?);
+        let off = traverse(&map, Slope::new(i64::MIN, 1), 0, false)?;
+        assert_eq!(off.exit, Exit::Left { row: 1 });
+        let off = traverse(&map, Slope::new(i64::MAX, -1), 0, false)?;
+        assert_eq!(off.exit, Exit::Right { row: 2 });
+
+        assert!(input_generator("..#\n.\n#..").is_err());
+        assert!(input_generator("..#\n\n#..").is_err());
+        let ragged = vec![
+            vec![Tile::Open, Tile::Open, Tile::Tree],
+            vec![Tile::Open],
+            vec![Tile::Tree, Tile::Open, Tile::Open],
+        ];
+        assert!(traverse(&ragged, Slope::new(2, 1), 0, true).is_err());
+        Ok(())
+    }
+    #[test]
+    fn test_rank_slopes() -> Result<()> {
+        let ex = "..##.......
+#...#...#..
+.#....#..#.
+..#.#...#.#
+.#...##..#.
+..#.##.....
+.#.#.#....#
+.#........#
+#.##...#...
+#...##....#
+.#..#...#.#";
+        let map = input_generator(ex)?;
+        let ranked = rank_slopes(&map, -11..=11, 1..=3)?;
+        assert_eq!(ranked.len(), 23 * 3);
+        for score in ranked.iter() {
+            assert_eq!(score.trees, traverse(&map, score.slope, 0, true)?.trees);
+        }
+        assert!(ranked
+            .windows(2)
+            .all(|w| (w[0].trees, w[0].length) <= (w[1].trees, w[1].length)));
+
+        let part2: Option<usize> = [(1, 1), (3, 1), (5, 1), (7, 1), (1, 2)]
+            .iter()
+            .map(|&(r, d)| {
+                ranked
+                    .iter()
+                    .find(|s| s.slope == Slope::new(r, d))
+                    .map(|s| s.trees)
+            })
+            .product();
+        assert_eq!(part2, Some(336));
+
+        let extreme = rank_slopes(&map, i64::MAX - 1..=i64::MAX, -1..=1)?;
+        assert_eq!(extreme.len(), 4);
+        for score in extreme.iter() {
+            assert_eq!(score.trees, traverse(&map, score.slope, 0, true)?.trees);
+            assert!(score.length.is_finite());
+        }
+
+        let ragged = vec![vec![Tile::Open], vec![Tile::Open, Tile::Tree]];
+        assert!(rank_slopes(&ragged, 1..=1, 1..=1).is_err());
+        Ok(())
+    }
+    #[test]
+    fn test_find_route() -> Result<()> {
+        let ex = "#.##
+#..#
+##.#
+##.#";
+        let map = input_generator(ex)?;
+        for search in [Search::Dijkstra, Search::AStar].iter() {
+            let route = find_route(&map, &MoveSet::orthogonal(), *search)?.expect("No route");
+            assert_eq!(route.trees, 0);
+            assert_eq!(route.path, vec![(1, 0), (1, 1), (2, 1), (2, 2), (2, 3)]);
+        }
+
+        // The knight has to land on a tree in the bottom row, wrapping around to get there
+        let knight = find_route(&map, &MoveSet::knight(), Search::AStar)?.expect("No route");
+        assert_eq!(knight.trees, 1);
+        assert_eq!(knight.path, vec![(1, 0), (2, 2), (0, 3)]);
+
+        let stuck = MoveSet(vec![(1, 0)]);
+        assert_eq!(find_route(&map, &stuck, Search::Dijkstra)?, None);
+
+        let ragged = vec![vec![Tile::Open, Tile::Open], vec![Tile::Open]];
+        assert!(find_route(&ragged, &MoveSet::orthogonal(), Search::AStar).is_err());
+        Ok(())
+    }
 }
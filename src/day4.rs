@@ -4,6 +4,8 @@ use lazy_static::lazy_static;
 use regex::Regex;
 use std::collections::HashMap;
 use std::convert::TryFrom;
+use std::fmt;
+use std::io::{self, BufRead};
 use std::str::FromStr;
 #[derive(Debug)]
 enum HeightMeasure {
@@ -11,6 +13,16 @@ enum HeightMeasure {
     Inch,
 }
 
+impl HeightMeasure {
+    fn to_mm(&self, n: u32) -> u32 {
+        match self {
+            HeightMeasure::Cm => n * 10,
+            // 25.4mm to the inch, rounded to the nearest mm
+            HeightMeasure::Inch => (n * 254 + 5) / 10,
+        }
+    }
+}
+
 impl FromStr for HeightMeasure {
     type Err = anyhow::Error;
 
@@ -23,8 +35,8 @@ impl FromStr for HeightMeasure {
     }
 }
 
-#[derive(Debug)]
-enum EyeColour {
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EyeColour {
     Amber,
     Blue,
     Brown,
@@ -51,134 +63,547 @@ impl FromStr for EyeColour {
     }
 }
 
-#[derive(Debug)]
-struct ValidPassport {
-    byr: u32,
-    iyr: u32,
-    eyr: u32,
-    hgt: u32,
-    hgt_type: HeightMeasure,
-    hcl: String,
-    ecl: EyeColour,
-    pid: u32,
-}
-
-impl TryFrom<&HashMap<String, String>> for ValidPassport {
+impl EyeColour {
+    /// The three letter code used in passport records.
+    pub fn code(self) -> &'static str {
+        match self {
+            Self::Amber => "amb",
+            Self::Blue => "blu",
+            Self::Brown => "brn",
+            Self::Grey => "gry",
+            Self::Green => "grn",
+            Self::Hazel => "hzl",
+            Self::Other => "oth",
+        }
+    }
+}
+
+/// A passport that passed the part 2 rules, with its fields normalized.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Passport {
+    pub byr: u32,
+    pub iyr: u32,
+    pub eyr: u32,
+    /// Height in millimetres, whichever unit the record used
+    pub height_mm: u32,
+    /// Hair colour as `#rrggbb`
+    pub hcl: String,
+    pub ecl: EyeColour,
+    /// Passport ID, keeping any leading zeros
+    pub pid: String,
+    pub cid: Option<String>,
+}
+
+impl Passport {
+    /// Validates a record against the full part 2 rules, reporting every
+    /// field that breaks them rather than stopping at the first.
+    pub fn strict(hm: &HashMap<String, String>) -> Result<Self, Vec<FieldError>> {
+        lazy_static! {
+            static ref PASSPORT_SCHEMA: Schema = Schema::passport();
+        }
+        PASSPORT_SCHEMA.validate(hm)?;
+
+        // The schema has checked every field, so these parses cannot fail
+        let get = |k: &str| hm[k].as_str();
+        let hgt_raw = get("hgt");
+        let (hgt, hgt_type) = hgt_raw.split_at(
+            hgt_raw
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(hgt_raw.len()),
+        );
+        Ok(Self {
+            byr: get("byr").parse().expect("Validated byr"),
+            iyr: get("iyr").parse().expect("Validated iyr"),
+            eyr: get("eyr").parse().expect("Validated eyr"),
+            height_mm: HeightMeasure::from_str(hgt_type)
+                .expect("Validated hgt unit")
+                .to_mm(hgt.parse().expect("Validated hgt")),
+            hcl: get("hcl").to_string(),
+            ecl: EyeColour::from_str(get("ecl")).expect("Validated ecl"),
+            pid: get("pid").to_string(),
+            cid: hm.get("cid").cloned(),
+        })
+    }
+}
+
+impl TryFrom<&HashMap<String, String>> for Passport {
     type Error = anyhow::Error;
     fn try_from(hm: &HashMap<String, String>) -> Result<Self> {
-        let byr_raw = hm.get("byr").ok_or(anyhow!("No byr"))?;
-        let byr: u32 = byr_raw.parse()?;
-        if byr < 1920 || byr > 2002 {
-            return Err(anyhow!("Bad byr: {}", byr));
-        }
+        Self::strict(hm).map_err(|errors| {
+            let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+            anyhow!(errors.join("; "))
+        })
+    }
+}
 
-        let iyr_raw = hm.get("iyr").ok_or(anyhow!("No iyr"))?;
-        let iyr: u32 = iyr_raw.parse()?;
-        if iyr < 2010 || iyr > 2020 {
-            return Err(anyhow!("Bad iyr: {}", iyr));
+/// The rule a field value broke.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    Missing,
+    NotANumber,
+    WrongDigits { expected: usize },
+    OutOfRange { min: i64, max: i64 },
+    UnknownUnit { allowed: Vec<String> },
+    NoMatch { pattern: String },
+    NotOneOf { allowed: Vec<String> },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::Missing => write!(f, "missing"),
+            Violation::NotANumber => write!(f, "not a number"),
+            Violation::WrongDigits { expected } => write!(f, "expected {} digits", expected),
+            Violation::OutOfRange { min, max } => write!(f, "outside {}-{}", min, max),
+            Violation::UnknownUnit { allowed } => {
+                write!(f, "unit must be one of {}", allowed.join(", "))
+            }
+            Violation::NoMatch { pattern } => write!(f, "does not match {}", pattern),
+            Violation::NotOneOf { allowed } => write!(f, "not one of {}", allowed.join(", ")),
         }
+    }
+}
 
-        let eyr_raw = hm.get("eyr").ok_or(anyhow!("No eyr"))?;
-        let eyr: u32 = eyr_raw.parse()?;
-        if eyr < 2020 || eyr > 2030 {
-            return Err(anyhow!("Bad eyr: {}", eyr));
+/// A field of a record that failed validation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldError {
+    pub field: String,
+    /// The raw value, `None` if the field was missing
+    pub value: Option<String>,
+    pub violation: Violation,
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.value {
+            Some(v) => write!(f, "{} {:?}: {}", self.field, v, self.violation),
+            None => write!(f, "{}: {}", self.field, self.violation),
         }
+    }
+}
 
-        let hgt_raw = hm.get("hgt").ok_or(anyhow!("No hgt"))?;
-        lazy_static! {
-            static ref hgt_regex: Regex =
-                Regex::new(r"(?P<h>[0-9]{2,3})(?P<t>in|cm)").expect("Bad regex");
+impl std::error::Error for FieldError {}
+
+/// The expected shape of one field's value.
+#[derive(Debug, Clone)]
+pub enum FieldType {
+    /// Any value
+    Text,
+    /// A whole number within `min..=max`, optionally with an exact number of digits
+    Integer {
+        min: i64,
+        max: i64,
+        digits: Option<usize>,
+    },
+    /// A whole number directly followed by one of the units, each with its own range
+    Measure(Vec<(String, i64, i64)>),
+    /// A regex the entire value must match
+    Pattern(Regex),
+    /// One of a fixed set of values
+    OneOf(Vec<String>),
+}
+
+#[derive(Debug, Clone)]
+pub struct FieldSpec {
+    pub name: String,
+    pub required: bool,
+    pub field_type: FieldType,
+}
+
+/// A declarative description of a record type, either built in code with
+/// [`Schema::field`] or parsed from text:
+///
+/// ```text
+/// [byr]
+/// type = integer
+/// range = 1920-2002
+/// digits = 4
+///
+/// [hgt]
+/// type = measure
+/// unit cm = 150-193
+/// unit in = 59-76
+///
+/// [hcl]
+/// type = pattern
+/// pattern = #[0-9a-f]{6}
+///
+/// [ecl]
+/// type = one_of
+/// values = amb blu brn gry grn hzl oth
+///
+/// [cid]
+/// required = false
+/// ```
+///
+/// Fields default to required text. Lines starting with `;` are comments.
+#[derive(Debug, Clone, Default)]
+pub struct Schema {
+    pub fields: Vec<FieldSpec>,
+}
+
+fn anchored(pattern: &str) -> Result<Regex> {
+    Ok(Regex::new(&format!("^(?:{})$", pattern))?)
+}
+
+impl Schema {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn field(mut self, name: &str, required: bool, field_type: FieldType) -> Self {
+        self.fields.push(FieldSpec {
+            name: name.to_string(),
+            required,
+            field_type,
+        });
+        self
+    }
+
+    /// The passport rules from part 2.
+    pub fn passport() -> Self {
+        let year = |min, max| FieldType::Integer {
+            min,
+            max,
+            digits: Some(4),
+        };
+        Self::new()
+            .field("byr", true, year(1920, 2002))
+            .field("iyr", true, year(2010, 2020))
+            .field("eyr", true, year(2020, 2030))
+            .field(
+                "hgt",
+                true,
+                FieldType::Measure(vec![
+                    ("cm".to_string(), 150, 193),
+                    ("in".to_string(), 59, 76),
+                ]),
+            )
+            .field(
+                "hcl",
+                true,
+                FieldType::Pattern(anchored("#[0-9a-f]{6}").expect("Bad regex")),
+            )
+            .field(
+                "ecl",
+                true,
+                FieldType::OneOf(
+                    ["amb", "blu", "brn", "gry", "grn", "hzl", "oth"]
+                        .iter()
+                        .map(|s| s.to_string())
+                        .collect(),
+                ),
+            )
+            .field(
+                "pid",
+                true,
+                FieldType::Pattern(anchored("[0-9]{9}").expect("Bad regex")),
+            )
+            .field("cid", false, FieldType::Text)
+    }
+
+    /// Checks a record against every field in the schema, collecting every
+    /// violation. Fields not in the schema are ignored.
+    pub fn validate(&self, record: &HashMap<String, String>) -> Result<(), Vec<FieldError>> {
+        let errors: Vec<FieldError> = self
+            .fields
+            .iter()
+            .filter_map(|spec| {
+                let violation = match record.get(&spec.name) {
+                    Some(v) => spec.field_type.check(v).err()?,
+                    None if spec.required => Violation::Missing,
+                    None => return None,
+                };
+                Some(FieldError {
+                    field: spec.name.clone(),
+                    value: record.get(&spec.name).cloned(),
+                    violation,
+                })
+            })
+            .collect();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
         }
-        let caps = hgt_regex
-            .captures(hgt_raw)
-            .ok_or(anyhow!("No hgt captures"))?;
-        let hgt: u32 = caps
-            .get(1)
-            .ok_or(anyhow!("No hgt capture"))?
-            .as_str()
-            .parse()?;
-        let hgt_type =
-            HeightMeasure::from_str(caps.get(2).ok_or(anyhow!("No hgt type capture"))?.as_str())?;
-        match hgt_type {
-            HeightMeasure::Cm => {
-                if hgt < 150 || hgt > 193 {
-                    return Err(anyhow!("Bad height number: {}", hgt));
+    }
+}
+
+fn parse_bounds(s: &str) -> Result<(i64, i64)> {
+    let mut split = s.trim().splitn(2, '-');
+    let min = split
+        .next()
+        .ok_or_else(|| anyhow!("No min"))?
+        .trim()
+        .parse()?;
+    let max = split
+        .next()
+        .ok_or_else(|| anyhow!("No - in range: {}", s))?
+        .trim()
+        .parse()?;
+    Ok((min, max))
+}
+
+impl FieldType {
+    fn check(&self, value: &str) -> Result<(), Violation> {
+        let number = |s: &str| {
+            if s.is_empty() || !s.chars().all(|c| c.is_ascii_digit()) {
+                return Err(Violation::NotANumber);
+            }
+            s.parse::<i64>().map_err(|_| Violation::NotANumber)
+        };
+        let in_range = |n: i64, min: i64, max: i64| {
+            if (min..=max).contains(&n) {
+                Ok(())
+            } else {
+                Err(Violation::OutOfRange { min, max })
+            }
+        };
+        match self {
+            FieldType::Text => Ok(()),
+            FieldType::Integer { min, max, digits } => {
+                let n = number(value)?;
+                if let Some(d) = digits {
+                    if value.len() != *d {
+                        return Err(Violation::WrongDigits { expected: *d });
+                    }
                 }
+                in_range(n, *min, *max)
+            }
+            FieldType::Measure(units) => {
+                let split = value
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(value.len());
+                let (n, unit) = value.split_at(split);
+                let n = number(n)?;
+                let (_, min, max) =
+                    units
+                        .iter()
+                        .find(|u| u.0 == unit)
+                        .ok_or_else(|| Violation::UnknownUnit {
+                            allowed: units.iter().map(|u| u.0.clone()).collect(),
+                        })?;
+                in_range(n, *min, *max)
             }
-            HeightMeasure::Inch => {
-                if hgt < 59 || hgt > 76 {
-                    return Err(anyhow!("Bad height number: {}", hgt));
+            FieldType::Pattern(re) => {
+                if re.is_match(value) {
+                    Ok(())
+                } else {
+                    Err(Violation::NoMatch {
+                        pattern: re.to_string(),
+                    })
                 }
             }
-        };
+            FieldType::OneOf(values) => {
+                if values.iter().any(|v| v == value) {
+                    Ok(())
+                } else {
+                    Err(Violation::NotOneOf {
+                        allowed: values.clone(),
+                    })
+                }
+            }
+        }
+    }
+}
 
-        let hcl_raw = hm.get("hcl").ok_or(anyhow!("No hcl"))?;
+// One `[field]` block of a schema file before its settings are interpreted
+struct SchemaSection {
+    name: String,
+    required: bool,
+    type_name: String,
+    settings: Vec<(String, String)>,
+}
 
-        lazy_static! {
-            static ref hcl_regex: Regex = Regex::new(r"#(?P<hex>[0-9a-f]+)").expect("Bad regex");
+impl FromStr for Schema {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut sections: Vec<SchemaSection> = Vec::new();
+        for (i, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(';') {
+                continue;
+            }
+            if line.starts_with('[') && line.ends_with(']') {
+                let name = line[1..line.len() - 1].trim().to_string();
+                sections.push(SchemaSection {
+                    name,
+                    required: true,
+                    type_name: "text".to_string(),
+                    settings: Vec::new(),
+                });
+                continue;
+            }
+            let section = sections
+                .last_mut()
+                .ok_or_else(|| anyhow!("Line {}: setting outside of a [field] section", i + 1))?;
+            let mut kv = line.splitn(2, '=');
+            let key = kv.next().unwrap_or("").trim().to_string();
+            let value = kv
+                .next()
+                .ok_or_else(|| anyhow!("Line {}: expected key = value", i + 1))?
+                .trim()
+                .to_string();
+            match key.as_str() {
+                "required" => section.required = value.parse()?,
+                "type" => section.type_name = value,
+                _ => section.settings.push((key, value)),
+            }
         }
-        let caps = hcl_regex
-            .captures(hcl_raw)
-            .ok_or(anyhow!("No hcl captures"))?;
-        let hcl: String = caps
-            .get(1)
-            .ok_or(anyhow!("No hcl capture"))?
-            .as_str()
-            .to_string();
-        if hcl.len() != 6 {
-            return Err(anyhow!("Bad hcl length: {}", hcl));
+
+        let mut schema = Schema::new();
+        for SchemaSection {
+            name,
+            required,
+            type_name,
+            settings,
+        } in sections
+        {
+            let get = |key: &str| {
+                settings
+                    .iter()
+                    .find(|s| s.0 == key)
+                    .map(|s| s.1.as_str())
+                    .ok_or_else(|| anyhow!("Field {} has no {}", name, key))
+            };
+            let field_type = match type_name.as_str() {
+                "text" => FieldType::Text,
+                "integer" => {
+                    let (min, max) = parse_bounds(get("range")?)?;
+                    let digits = match get("digits") {
+                        Ok(d) => Some(d.parse()?),
+                        Err(_) => None,
+                    };
+                    FieldType::Integer { min, max, digits }
+                }
+                "measure" => FieldType::Measure(
+                    settings
+                        .iter()
+                        .filter(|s| s.0.starts_with("unit "))
+                        .map(|s| {
+                            let (min, max) = parse_bounds(&s.1)?;
+                            Ok((s.0["unit ".len()..].trim().to_string(), min, max))
+                        })
+                        .collect::<Result<_>>()?,
+                ),
+                "pattern" => FieldType::Pattern(anchored(get("pattern")?)?),
+                "one_of" => FieldType::OneOf(
+                    get("values")?
+                        .split_whitespace()
+                        .map(|v| v.to_string())
+                        .collect(),
+                ),
+                t => return Err(anyhow!("Field {} has unknown type: {}", name, t)),
+            };
+            schema = schema.field(&name, required, field_type);
         }
+        Ok(schema)
+    }
+}
 
-        let ecl_raw = hm.get("ecl").ok_or(anyhow!("No ecl"))?;
-        let ecl = EyeColour::from_str(ecl_raw)?;
+#[derive(Debug)]
+pub enum RecordError {
+    Io(io::Error),
+    /// A pair with no `:` or an empty key, on a 1-indexed line
+    MalformedPair {
+        line: usize,
+        pair: String,
+    },
+}
 
-        let pid_raw = hm.get("pid").ok_or(anyhow!("No pid"))?;
-        lazy_static! {
-            static ref pid_regex: Regex = Regex::new(r"(?P<id>[0-9]+)").expect("Bad regex");
+impl fmt::Display for RecordError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecordError::Io(e) => write!(f, "Read failed: {}", e),
+            RecordError::MalformedPair { line, pair } => {
+                write!(f, "Line {}: expected key:value, got {:?}", line, pair)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RecordError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RecordError::Io(e) => Some(e),
+            RecordError::MalformedPair { .. } => None,
         }
-        let caps = pid_regex
-            .captures(pid_raw)
-            .ok_or(anyhow!("No pid captures"))?;
-        let pid = caps.get(1).ok_or(anyhow!("No pid capture"))?.as_str();
+    }
+}
 
-        if pid.len() != 9 {
-            return Err(anyhow!("Bad pid length: {}", pid));
+/// Reads blank-line separated records of `key:value` pairs one at a time,
+/// holding only the current line and record in memory. Accepts `\n` and
+/// `\r\n` line endings. After a malformed pair the rest of that record is
+/// skipped.
+pub struct RecordReader<R> {
+    reader: R,
+    line: String,
+    line_number: usize,
+}
+
+impl<R: BufRead> RecordReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            line: String::new(),
+            line_number: 0,
         }
+    }
+}
 
-        let pid: u32 = pid.parse()?;
+impl<R: BufRead> Iterator for RecordReader<R> {
+    type Item = Result<HashMap<String, String>, RecordError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut record = HashMap::with_capacity(8);
+        let mut error = None;
+        loop {
+            self.line.clear();
+            let read = match self.reader.read_line(&mut self.line) {
+                Ok(n) => n,
+                Err(e) => return Some(Err(RecordError::Io(e))),
+            };
+            self.line_number += 1;
+            let line = self.line.trim_end_matches(&['\n', '\r'][..]);
+
+            if read == 0 || line.trim().is_empty() {
+                if let Some(e) = error {
+                    return Some(Err(e));
+                }
+                if !record.is_empty() {
+                    return Some(Ok(record));
+                }
+                if read == 0 {
+                    return None;
+                }
+                continue;
+            }
+            if error.is_some() {
+                continue;
+            }
 
-        Ok(Self {
-            byr,
-            iyr,
-            eyr,
-            hgt,
-            hgt_type,
-            hcl,
-            ecl,
-            pid,
-        })
+            for pair in line.split_whitespace() {
+                match pair.find(':') {
+                    Some(i) if i > 0 => {
+                        record.insert(pair[..i].to_string(), pair[i + 1..].to_string());
+                    }
+                    _ => {
+                        error = Some(RecordError::MalformedPair {
+                            line: self.line_number,
+                            pair: pair.to_string(),
+                        });
+                        break;
+                    }
+                }
+            }
+        }
     }
 }
 
 #[aoc_generator(day4)]
-pub fn input_generator(input: &str) -> Vec<HashMap<String, String>> {
-    let re = Regex::new(r"\n(?P<c>[^\n])").expect("Bad regex");
-    let input = re.replace_all(input, " ${c}");
-    input
-        .lines()
-        .map(|l| {
-            let mut hm = HashMap::with_capacity(8);
-            let pairs = l.trim().split(' ');
-            pairs.for_each(|p| {
-                let mut splitp = p.split(':');
-                let key = splitp.next().expect("No key");
-                let val = splitp.next().expect("No val");
-                // (key.to_string(), val.to_string())
-                hm.insert(key.to_string(), val.to_string());
-            });
-            hm
-        })
-        .collect()
+pub fn input_generator(input: &str) -> Result<Vec<HashMap<String, String>>> {
+    Ok(RecordReader::new(input.as_bytes()).collect::<Result<_, _>>()?)
 }
 
 #[aoc(day4, part1)]
@@ -200,7 +625,7 @@ pub fn solve_part2(map: &[HashMap<String, String>]) -> usize {
 
     map.iter()
         .filter(|x| needed_keys.iter().all(|k| x.contains_key(*k)))
-        .map(|x| ValidPassport::try_from(x))
+        .map(|x| Passport::try_from(x))
         // .for_each(|x| {
         //     if let Ok(z) = x {
         //         println!("{:?}", z);
@@ -210,13 +635,155 @@ pub fn solve_part2(map: &[HashMap<String, String>]) -> usize {
         .count()
 }
 
+/// Lists every record failing the part 2 rules, by 0-indexed position, with
+/// all of its violations.
+pub fn rejections(map: &[HashMap<String, String>]) -> Vec<(usize, Vec<FieldError>)> {
+    map.iter()
+        .enumerate()
+        .filter_map(|(i, x)| Passport::strict(x).err().map(|e| (i, e)))
+        .collect()
+}
+
+#[aoc(day4, part2, Breakdown)]
+pub fn solve_part2_breakdown(map: &[HashMap<String, String>]) -> usize {
+    let rejected = rejections(map);
+    for (i, errors) in rejected.iter() {
+        println!("Passport {} rejected:", i);
+        for e in errors {
+            println!("  {}", e);
+        }
+    }
+    map.len() - rejected.len()
+}
+
+/// All records passing the part 2 rules, normalized.
+pub fn normalize(map: &[HashMap<String, String>]) -> Vec<Passport> {
+    map.iter()
+        .filter_map(|x| Passport::strict(x).ok())
+        .collect()
+}
+
+/// Differing passports sharing a passport ID.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PidConflict {
+    pub pid: String,
+    /// Each distinct version, in input order
+    pub passports: Vec<Passport>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Deduplicated {
+    /// Passports whose ID is unique once exact copies are merged
+    pub passports: Vec<Passport>,
+    pub conflicts: Vec<PidConflict>,
+}
+
+/// Merges exact duplicates, and sets aside passports that share an ID but
+/// disagree on any field so they are not mistaken for one identity.
+pub fn dedupe(passports: &[Passport]) -> Deduplicated {
+    let mut order: Vec<&str> = Vec::new();
+    let mut by_pid: HashMap<&str, Vec<&Passport>> = HashMap::new();
+    for p in passports {
+        let versions = by_pid.entry(&p.pid).or_insert_with(|| {
+            order.push(&p.pid);
+            Vec::new()
+        });
+        if !versions.contains(&p) {
+            versions.push(p);
+        }
+    }
+
+    let mut out = Deduplicated {
+        passports: Vec::new(),
+        conflicts: Vec::new(),
+    };
+    for pid in order {
+        let versions = &by_pid[pid];
+        if versions.len() == 1 {
+            out.passports.push(versions[0].clone());
+        } else {
+            out.conflicts.push(PidConflict {
+                pid: pid.to_string(),
+                passports: versions.iter().map(|p| (*p).clone()).collect(),
+            });
+        }
+    }
+    out
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+impl Passport {
+    /// The passport as a single-line JSON object.
+    pub fn to_json(&self) -> String {
+        format!(
+            r#"{{"byr":{},"iyr":{},"eyr":{},"height_mm":{},"hcl":{},"ecl":{},"pid":{},"cid":{}}}"#,
+            self.byr,
+            self.iyr,
+            self.eyr,
+            self.height_mm,
+            json_string(&self.hcl),
+            json_string(self.ecl.code()),
+            json_string(&self.pid),
+            self.cid
+                .as_deref()
+                .map_or_else(|| "null".to_string(), json_string)
+        )
+    }
+}
+
+/// Exports passports as a JSON array, one object per line.
+pub fn to_json(passports: &[Passport]) -> String {
+    let objects: Vec<String> = passports.iter().map(|p| p.to_json()).collect();
+    format!("[\n{}\n]\n", objects.join(",\n"))
+}
+
+/// Exports passports as CSV with a header row.
+pub fn to_csv(passports: &[Passport]) -> String {
+    let mut out = String::from("byr,iyr,eyr,height_mm,hcl,ecl,pid,cid\n");
+    for p in passports {
+        // Only cid is free text, every other field is validated to be CSV safe
+        let cid = p.cid.as_deref().unwrap_or("");
+        let cid = if cid.contains(&[',', '"', '\n', '\r'][..]) {
+            format!("\"{}\"", cid.replace('"', "\"\""))
+        } else {
+            cid.to_string()
+        };
+        out.push_str(&format!(
+            "{},{},{},{},{},{},{},{}\n",
+            p.byr,
+            p.iyr,
+            p.eyr,
+            p.height_mm,
+            p.hcl,
+            p.ecl.code(),
+            p.pid,
+            cid
+        ));
+    }
+    out
+}
+
 #[cfg(test)]
 mod tests {
     // Note this useful idiom: importing names from outer (for mod tests) scope.
     use super::*;
 
     #[test]
-    fn test1() {
+    fn test1() -> Result<()> {
         let ex = "ecl:gry pid:860033327 eyr:2020 hcl:#fffffd
 byr:1937 iyr:2017 cid:147 hgt:183cm
 
@@ -230,11 +797,12 @@ hgt:179cm
 
 hcl:#cfa07d eyr:2025 pid:166559648
 iyr:2011 ecl:brn hgt:59in";
-        let input = input_generator(ex);
+        let input = input_generator(ex)?;
         assert_eq!(solve_part1(&input), 2);
+        Ok(())
     }
     #[test]
-    fn test2() {
+    fn test2() -> Result<()> {
         let ex = "eyr:1972 cid:100
 hcl:#18171d ecl:amb hgt:170 pid:186cm iyr:2018 byr:1926
 
@@ -248,11 +816,12 @@ ecl:brn hgt:182cm pid:021572410 eyr:2020 byr:1992 cid:277
 hgt:59cm ecl:zzz
 eyr:2038 hcl:74454a iyr:2023
 pid:3556412378 byr:2007";
-        let input = input_generator(ex);
+        let input = input_generator(ex)?;
         assert_eq!(solve_part2(&input), 0);
+        Ok(())
     }
     #[test]
-    fn test3() {
+    fn test3() -> Result<()> {
         let ex = "pid:087499704 hgt:74in ecl:grn iyr:2012 eyr:2030 byr:1980
 hcl:#623a2f
 
@@ -265,7 +834,147 @@ pid:545766238 ecl:hzl
 eyr:2022
 
 iyr:2010 hgt:158cm hcl:#b6652a ecl:blu byr:1944 eyr:2021 pid:093154719";
-        let input = input_generator(ex);
+        let input = input_generator(ex)?;
         assert_eq!(solve_part2(&input), 4);
+        Ok(())
+    }
+    #[test]
+    fn test_schema() -> Result<()> {
+        let ex = "pid:087499704 hgt:74in ecl:grn iyr:2012 eyr:2030 byr:1980
+hcl:#623a2f
+
+eyr:1972 cid:100
+hcl:#18171d ecl:amb hgt:170 pid:186cm iyr:2018 byr:1926
+
+hcl:#888785
+hgt:164cm byr:2001 iyr:2015 cid:88
+pid:545766238 ecl:hzl
+eyr:2022
+
+hgt:190cmxyz ecl:amb eyr:2025 hcl:#123abc iyr:2015 byr:1990 pid:000000001";
+        let input = input_generator(ex)?;
+        let builtin = Schema::passport();
+        let valid: Vec<bool> = input.iter().map(|r| builtin.validate(r).is_ok()).collect();
+        assert_eq!(valid, vec![true, false, true, false]);
+        let valid: Vec<bool> = input
+            .iter()
+            .map(|r| Passport::try_from(r).is_ok())
+            .collect();
+        assert_eq!(valid, vec![true, false, true, false]);
+
+        let text = "; Passport fields
+[byr]
+type = integer
+range = 1920-2002
+digits = 4
+[iyr]
+type = integer
+range = 2010-2020
+[eyr]
+type = integer
+range = 2020-2030
+[hgt]
+type = measure
+unit cm = 150-193
+unit in = 59-76
+[hcl]
+type = pattern
+pattern = #[0-9a-f]{6}
+[ecl]
+type = one_of
+values = amb blu brn gry grn hzl oth
+[pid]
+type = pattern
+pattern = [0-9]{9}
+[cid]
+required = false";
+        let parsed = Schema::from_str(text)?;
+        let valid: Vec<bool> = input.iter().map(|r| parsed.validate(r).is_ok()).collect();
+        assert_eq!(valid, vec![true, false, true, false]);
+
+        assert!(Schema::from_str("[x]\ntype = colour").is_err());
+        assert!(Schema::from_str("type = text").is_err());
+        Ok(())
+    }
+    #[test]
+    fn test_strict() -> Result<()> {
+        let ex = "hgt:190cmxyz ecl:amb eyr:2025 hcl:#123abcd iyr:2015 byr:1990 pid:x000000001
+
+hcl:#623a2f pid:087499704 hgt:74in ecl:grn iyr:2012 eyr:2030 byr:1980";
+        let input = input_generator(ex)?;
+        let rejected = rejections(&input);
+        assert_eq!(rejected.len(), 1);
+        let (index, errors) = &rejected[0];
+        assert_eq!(*index, 0);
+        let fields: Vec<&str> = errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, vec!["hgt", "hcl", "pid"]);
+        assert_eq!(
+            errors[0],
+            FieldError {
+                field: "hgt".to_string(),
+                value: Some("190cmxyz".to_string()),
+                violation: Violation::UnknownUnit {
+                    allowed: vec!["cm".to_string(), "in".to_string()]
+                },
+            }
+        );
+        assert_eq!(
+            errors[2].to_string(),
+            "pid \"x000000001\": does not match ^(?:[0-9]{9})$"
+        );
+        assert_eq!(solve_part2(&input), input.len() - rejected.len());
+        Ok(())
+    }
+    #[test]
+    fn test_normalize() -> Result<()> {
+        let ex = "pid:087499704 hgt:74in ecl:grn iyr:2012 eyr:2030 byr:1980 hcl:#623a2f
+
+eyr:2029 ecl:blu cid:129 byr:1989 iyr:2014 pid:896056539 hcl:#a97842 hgt:165cm
+
+pid:087499704 hgt:74in ecl:grn iyr:2012 eyr:2030 byr:1980 hcl:#623a2f
+
+hcl:#888785 hgt:164cm byr:2001 iyr:2015 pid:896056539 ecl:hzl eyr:2022";
+        let passports = normalize(&input_generator(ex)?);
+        assert_eq!(passports.len(), 4);
+        assert_eq!(passports[0].height_mm, 1880);
+        assert_eq!(passports[0].pid, "087499704");
+        assert_eq!(passports[1].ecl, EyeColour::Blue);
+
+        let deduped = dedupe(&passports);
+        assert_eq!(deduped.passports, vec![passports[0].clone()]);
+        assert_eq!(deduped.conflicts.len(), 1);
+        assert_eq!(deduped.conflicts[0].pid, "896056539");
+        assert_eq!(deduped.conflicts[0].passports.len(), 2);
+
+        assert_eq!(
+            to_json(&deduped.passports),
+            concat!(
+                "[\n",
+                r##"{"byr":1980,"iyr":2012,"eyr":2030,"height_mm":1880,"hcl":"#623a2f","ecl":"grn","pid":"087499704","cid":null}"##,
+                "\n]\n"
+            )
+        );
+        assert_eq!(
+            to_csv(&passports[1..2]),
+            "byr,iyr,eyr,height_mm,hcl,ecl,pid,cid\n1989,2014,2029,1650,#a97842,blu,896056539,129\n"
+        );
+        Ok(())
+    }
+    #[test]
+    fn test_record_reader() {
+        let ex = "ecl:gry pid:860033327\r\nbyr:1937\r\n\r\n\r\nhcl:#cfa07d oops byr:1929\r\niyr:2013\r\n\r\niyr:2011";
+        let records: Vec<_> = RecordReader::new(ex.as_bytes()).collect();
+        assert_eq!(records.len(), 3);
+        let first = records[0].as_ref().expect("First record");
+        assert_eq!(first.len(), 3);
+        assert_eq!(first["byr"], "1937");
+        match &records[1] {
+            Err(RecordError::MalformedPair { line, pair }) => {
+                assert_eq!((*line, pair.as_str()), (5, "oops"));
+            }
+            r => panic!("Expected malformed pair, got {:?}", r),
+        }
+        assert_eq!(records[2].as_ref().expect("Last record")["iyr"], "2011");
+        assert!(input_generator(ex).is_err());
     }
 }
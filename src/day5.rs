@@ -1,107 +1,168 @@
 use anyhow::{anyhow, Result};
 use aoc_runner_derive::{aoc, aoc_generator};
-use std::convert::TryFrom;
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
 
-pub enum RowDivision {
-    Back,
-    Front,
+/// The shape of a plane and how its boarding passes are written: `row_bits`
+/// letters picking the row by binary space partitioning, then `col_bits`
+/// letters picking the column. Built with [`PlaneLayout::new`], which keeps
+/// seat IDs within a `u32`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlaneLayout {
+    row_bits: u32,
+    col_bits: u32,
+    /// Letters choosing the (lower, upper) half of the remaining rows
+    row_letters: (char, char),
+    col_letters: (char, char),
 }
 
-pub enum SeatDivision {
-    Right,
-    Left,
+impl Default for PlaneLayout {
+    fn default() -> Self {
+        Self {
+            row_bits: 7,
+            col_bits: 3,
+            row_letters: ('F', 'B'),
+            col_letters: ('L', 'R'),
+        }
+    }
 }
 
-impl TryFrom<char> for RowDivision {
-    type Error = anyhow::Error;
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Seat {
+    pub row: u32,
+    pub col: u32,
+    pub id: u32,
+}
 
-    fn try_from(c: char) -> Result<Self, Self::Error> {
-        match c {
-            'F' => Ok(Self::Front),
-            'B' => Ok(Self::Back),
-            _ => Err(anyhow!("Bad row char: {}", c)),
+impl PlaneLayout {
+    pub fn new(
+        row_bits: u32,
+        col_bits: u32,
+        row_letters: (char, char),
+        col_letters: (char, char),
+    ) -> Result<Self> {
+        // Seat IDs run up to 2^(row_bits + col_bits) - 1
+        if row_bits + col_bits > 31 {
+            return Err(anyhow!(
+                "{} row bits and {} column bits do not fit a u32 seat ID",
+                row_bits,
+                col_bits
+            ));
+        }
+        for (lower, upper) in [row_letters, col_letters] {
+            if lower == upper {
+                return Err(anyhow!("Letter {} cannot mean both halves", lower));
+            }
         }
+        Ok(Self {
+            row_bits,
+            col_bits,
+            row_letters,
+            col_letters,
+        })
     }
-}
 
-impl TryFrom<char> for SeatDivision {
-    type Error = anyhow::Error;
+    pub fn row_bits(&self) -> u32 {
+        self.row_bits
+    }
 
-    fn try_from(c: char) -> Result<Self, Self::Error> {
-        match c {
-            'R' => Ok(Self::Right),
-            'L' => Ok(Self::Left),
-            _ => Err(anyhow!("Bad seat char: {}", c)),
-        }
+    pub fn col_bits(&self) -> u32 {
+        self.col_bits
     }
-}
 
-#[aoc_generator(day5)]
-pub fn input_generator(input: &str) -> Result<Vec<(Vec<RowDivision>, Vec<SeatDivision>)>> {
-    input
-        .lines()
-        .map(|l| {
-            let (rowpart, seatpart) = l.split_at(7);
-            let rowvec = rowpart
-                .chars()
-                .map(|c| RowDivision::try_from(c))
-                .collect::<Result<Vec<RowDivision>>>();
-            let seatvec = seatpart
-                .chars()
-                .map(|c| SeatDivision::try_from(c))
-                .collect::<Result<Vec<SeatDivision>>>();
-            Ok((rowvec?, seatvec?))
-        })
-        .collect()
-}
+    pub fn row_letters(&self) -> (char, char) {
+        self.row_letters
+    }
 
-fn get_seat_id(inp: &(Vec<RowDivision>, Vec<SeatDivision>)) -> u32 {
-    let mut start = 0;
-    let mut end = 127;
+    pub fn col_letters(&self) -> (char, char) {
+        self.col_letters
+    }
 
-    for r in inp.0.iter() {
-        match r {
-            RowDivision::Back => {
-                start += ((end + 1) - start) / 2;
-            }
-            RowDivision::Front => {
-                end -= (end - start) / 2;
-            }
+    pub fn rows(&self) -> u32 {
+        1 << self.row_bits
+    }
+
+    pub fn cols(&self) -> u32 {
+        1 << self.col_bits
+    }
+
+    pub fn pass_len(&self) -> usize {
+        (self.row_bits + self.col_bits) as usize
+    }
+
+    fn check_len(&self, pass: &str) -> Result<()> {
+        if pass.chars().count() == self.pass_len() {
+            Ok(())
+        } else {
+            Err(anyhow!(
+                "Pass {} should be {} letters long",
+                pass,
+                self.pass_len()
+            ))
         }
     }
 
-    let row = start;
-    start = 0;
-    end = 7;
-    for r in inp.1.iter() {
-        match r {
-            SeatDivision::Right => {
-                start += ((end + 1) - start) / 2;
-            }
-            SeatDivision::Left => {
-                end -= (end - start) / 2;
-            }
+    pub fn seat(&self, row: u32, col: u32) -> Seat {
+        Seat {
+            row,
+            col,
+            id: row * self.cols() + col,
         }
-        // println!("seat start: {}, end: {}", start, end);
     }
-    let seat = start;
 
-    // println!("Row: {}, Seat: {}", row, seat);
-    (row * 8) + seat
+    pub fn decode(&self, pass: &str) -> Result<Seat> {
+        self.check_len(pass)?;
+        let mut chars = pass.chars();
+        let mut read = |bits: u32, (lower, upper): (char, char)| {
+            chars.by_ref().take(bits as usize).try_fold(0, |acc, c| {
+                if c == lower {
+                    Ok(acc << 1)
+                } else if c == upper {
+                    Ok((acc << 1) | 1)
+                } else {
+                    Err(anyhow!("Bad letter {} in pass {}", c, pass))
+                }
+            })
+        };
+        let row = read(self.row_bits, self.row_letters)?;
+        let col = read(self.col_bits, self.col_letters)?;
+        Ok(self.seat(row, col))
+    }
+
+    pub fn encode(&self, id: u32) -> Result<String> {
+        if id >= self.rows() * self.cols() {
+            return Err(anyhow!("Seat ID {} is not on the plane", id));
+        }
+        let (row, col) = (id / self.cols(), id % self.cols());
+        let write = |value: u32, bits: u32, (lower, upper): (char, char)| {
+            (0..bits)
+                .rev()
+                .map(move |b| if value & (1 << b) == 0 { lower } else { upper })
+        };
+        Ok(write(row, self.row_bits, self.row_letters)
+            .chain(write(col, self.col_bits, self.col_letters))
+            .collect())
+    }
+}
+
+#[aoc_generator(day5)]
+pub fn input_generator(input: &str) -> Result<Vec<Seat>> {
+    let layout = PlaneLayout::default();
+    input.lines().map(|l| layout.decode(l)).collect()
 }
 
 #[aoc(day5, part1)]
-pub fn solve_part1(input: &[(Vec<RowDivision>, Vec<SeatDivision>)]) -> Result<u32> {
+pub fn solve_part1(input: &[Seat]) -> Result<u32> {
     input
         .iter()
-        .map(|x| get_seat_id(x))
+        .map(|s| s.id)
         .max()
         .ok_or_else(|| anyhow!("No maximum?"))
 }
 
 #[aoc(day5, part2)]
-pub fn solve_part2(input: &[(Vec<RowDivision>, Vec<SeatDivision>)]) -> Result<u32> {
-    let mut seat_ids: Vec<u32> = input.iter().map(|x| get_seat_id(x)).collect();
+pub fn solve_part2(input: &[Seat]) -> Result<u32> {
+    let mut seat_ids: Vec<u32> = input.iter().map(|s| s.id).collect();
     seat_ids.sort_unstable();
     let out = seat_ids.iter().try_fold(None, |acc, x| {
         if let Some(z) = acc {
@@ -122,6 +183,134 @@ pub fn solve_part2(input: &[(Vec<RowDivision>, Vec<SeatDivision>)]) -> Result<u3
     }
 }
 
+/// Seat usage on one flight, from its decoded boarding passes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Occupancy {
+    pub layout: PlaneLayout,
+    /// Rows that exist on this plane, some at the very front and back may not
+    pub rows: RangeInclusive<u32>,
+    /// Number of passes issued for each seat ID in `rows`, absent if none
+    pub passes_per_seat: HashMap<u32, u32>,
+    /// Passes naming a row outside `rows`
+    pub missing_rows: Vec<(String, Seat)>,
+    /// Passes that could not be decoded, with the reason
+    pub unreadable: Vec<(String, String)>,
+}
+
+impl Occupancy {
+    /// Fails if `rows` reaches past the back of the plane.
+    pub fn new(layout: &PlaneLayout, rows: RangeInclusive<u32>, passes: &[&str]) -> Result<Self> {
+        if !rows.is_empty() && *rows.end() >= layout.rows() {
+            return Err(anyhow!(
+                "Rows {:?} do not fit a plane of {} rows",
+                rows,
+                layout.rows()
+            ));
+        }
+        let mut out = Self {
+            layout: layout.clone(),
+            rows,
+            passes_per_seat: HashMap::new(),
+            missing_rows: Vec::new(),
+            unreadable: Vec::new(),
+        };
+        for pass in passes {
+            match layout.decode(pass) {
+                Ok(seat) if out.rows.contains(&seat.row) => {
+                    *out.passes_per_seat.entry(seat.id).or_insert(0) += 1
+                }
+                Ok(seat) => out.missing_rows.push((pass.to_string(), seat)),
+                Err(e) => out.unreadable.push((pass.to_string(), e.to_string())),
+            }
+        }
+        Ok(out)
+    }
+
+    fn seats(&self) -> impl Iterator<Item = Seat> + '_ {
+        self.rows
+            .clone()
+            .flat_map(move |row| (0..self.layout.cols()).map(move |col| self.layout.seat(row, col)))
+    }
+
+    fn passes(&self, id: u32) -> u32 {
+        self.passes_per_seat.get(&id).copied().unwrap_or(0)
+    }
+
+    fn seat_by_id(&self, id: u32) -> Seat {
+        self.layout
+            .seat(id / self.layout.cols(), id % self.layout.cols())
+    }
+
+    // Occupied seat IDs in order, so work scales with passes rather than seats
+    fn occupied_ids(&self) -> Vec<u32> {
+        let mut ids: Vec<u32> = self.passes_per_seat.keys().copied().collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    pub fn occupied(&self) -> Vec<Seat> {
+        self.occupied_ids()
+            .into_iter()
+            .map(|id| self.seat_by_id(id))
+            .collect()
+    }
+
+    /// Every seat in `rows` without a pass. Unlike the other queries this
+    /// visits every seat in `rows`.
+    pub fn vacant(&self) -> Vec<Seat> {
+        self.seats().filter(|s| self.passes(s.id) == 0).collect()
+    }
+
+    /// Vacant seats whose IDs either side are both taken, as in part 2.
+    pub fn vacant_between_occupied(&self) -> Vec<Seat> {
+        self.occupied_ids()
+            .into_iter()
+            .filter(|&id| {
+                id.checked_add(2)
+                    .is_some_and(|next| self.passes(id + 1) == 0 && self.passes(next) > 0)
+            })
+            .map(|id| self.seat_by_id(id + 1))
+            .collect()
+    }
+
+    /// Seats with more than one pass issued, and how many.
+    pub fn duplicates(&self) -> Vec<(Seat, u32)> {
+        self.occupied_ids()
+            .into_iter()
+            .map(|id| (self.seat_by_id(id), self.passes(id)))
+            .filter(|(_, n)| *n > 1)
+            .collect()
+    }
+
+    /// Draws the plane one row per line, front first, with an aisle down the
+    /// middle. `#` is occupied, `.` vacant and `X` has several passes.
+    pub fn render(&self) -> String {
+        let width = self.rows.end().to_string().len();
+        let cols = self.layout.cols();
+        let aisle = |col: u32| if col == cols / 2 { " " } else { "" };
+
+        let mut out = " ".repeat(width + 1);
+        for col in 0..cols {
+            out.push_str(aisle(col));
+            out.push(std::char::from_u32('A' as u32 + col % 26).unwrap_or('?'));
+        }
+        out.push('\n');
+        for row in self.rows.clone() {
+            out.push_str(&format!("{:>width$} ", row, width = width));
+            for col in 0..cols {
+                out.push_str(aisle(col));
+                out.push(match self.passes(self.layout.seat(row, col).id) {
+                    0 => '.',
+                    1 => '#',
+                    _ => 'X',
+                });
+            }
+            out.push('\n');
+        }
+        out
+    }
+}
+
 #[cfg(test)]
 mod tests {
     // Note this useful idiom: importing names from outer (for mod tests) scope.
@@ -131,28 +320,96 @@ mod tests {
     fn test1() -> Result<()> {
         let ex = "FBFBBFFRLR";
         let input = input_generator(ex)?;
-        assert_eq!(get_seat_id(&input[0]), 357);
+        assert_eq!(input[0].id, 357);
         Ok(())
     }
     #[test]
     fn test2() -> Result<()> {
         let ex = "BFFFBBFRRR";
         let input = input_generator(ex)?;
-        assert_eq!(get_seat_id(&input[0]), 567);
+        assert_eq!(input[0].id, 567);
         Ok(())
     }
     #[test]
     fn test3() -> Result<()> {
         let ex = "FFFBBBFRRR";
         let input = input_generator(ex)?;
-        assert_eq!(get_seat_id(&input[0]), 119);
+        assert_eq!(input[0].id, 119);
         Ok(())
     }
     #[test]
     fn test4() -> Result<()> {
         let ex = "BBFFBBFRLL";
         let input = input_generator(ex)?;
-        assert_eq!(get_seat_id(&input[0]), 820);
+        assert_eq!(input[0].id, 820);
+        Ok(())
+    }
+    #[test]
+    fn test_solve() -> Result<()> {
+        let input = input_generator("FBFBBFFRLR\nFBFBBFFRRL\nFBFBBFFRRR\nFBFBBFBLLL\nFBFBBFBLRL")?;
+        assert_eq!(solve_part1(&input)?, 362);
+        assert_eq!(solve_part2(&input)?, 361);
+        Ok(())
+    }
+    #[test]
+    fn test_layout() -> Result<()> {
+        let layout = PlaneLayout::default();
+        let seat = layout.decode("FBFBBFFRLR")?;
+        assert_eq!(seat, layout.seat(44, 5));
+        assert_eq!(seat.id, 357);
+        assert_eq!(layout.encode(357)?, "FBFBBFFRLR");
+        assert!(layout.decode("FBFBBFFRL").is_err());
+        assert!(layout.decode("FBFBBFFRLRR").is_err());
+        assert!(layout.decode("FBFBBFFRLF").is_err());
+        assert!(layout.encode(1024).is_err());
+        assert!(input_generator("FBFBBF").is_err());
+
+        let small = PlaneLayout::new(2, 1, ('a', 'b'), ('0', '1'))?;
+        assert_eq!(small.decode("ba1")?, small.seat(2, 1));
+        assert_eq!(small.decode("ba1")?.id, 5);
+        for id in 0..8 {
+            assert_eq!(small.decode(&small.encode(id)?)?.id, id);
+        }
+
+        assert!(PlaneLayout::new(30, 3, ('F', 'B'), ('L', 'R')).is_err());
+        assert!(PlaneLayout::new(7, 3, ('F', 'F'), ('L', 'R')).is_err());
+        let widest = PlaneLayout::new(28, 3, ('F', 'B'), ('L', 'R'))?;
+        assert_eq!(
+            widest.decode(&widest.encode(u32::MAX >> 1)?)?.id,
+            u32::MAX >> 1
+        );
+        Ok(())
+    }
+    #[test]
+    fn test_occupancy() -> Result<()> {
+        let layout = PlaneLayout::new(2, 2, ('F', 'B'), ('L', 'R'))?;
+        let passes = [
+            "FBLL", "FBLR", "FBRR", "BFLL", "BFLR", "BFRL", "BFRR", "BFLR", "FFLR", "FFX",
+        ];
+        let occupancy = Occupancy::new(&layout, 1..=2, &passes)?;
+        assert_eq!(occupancy.vacant(), vec![layout.seat(1, 2)]);
+        assert_eq!(occupancy.vacant_between_occupied(), vec![layout.seat(1, 2)]);
+        assert_eq!(occupancy.duplicates(), vec![(layout.seat(2, 1), 2)]);
+        assert_eq!(
+            occupancy.missing_rows,
+            vec![("FFLR".to_string(), layout.seat(0, 1))]
+        );
+        assert_eq!(occupancy.unreadable.len(), 1);
+        assert_eq!(occupancy.occupied().len(), 7);
+        assert_eq!(occupancy.render(), "  AB CD\n1 ## .#\n2 #X ##\n");
+
+        let short = PlaneLayout::new(1, 2, ('F', 'B'), ('L', 'R'))?;
+        assert!(Occupancy::new(&short, 0..=3, &passes).is_err());
+        assert_eq!(Occupancy::new(&short, 0..=1, &[])?.vacant().len(), 8);
+
+        // Only the passes given are stored, however large the plane
+        let huge = PlaneLayout::new(28, 3, ('F', 'B'), ('L', 'R'))?;
+        let passes = [huge.encode(40)?, huge.encode(42)?, huge.encode(42)?];
+        let passes: Vec<&str> = passes.iter().map(|p| p.as_str()).collect();
+        let occupancy = Occupancy::new(&huge, 0..=huge.rows() - 1, &passes)?;
+        assert_eq!(occupancy.passes_per_seat.len(), 2);
+        assert_eq!(occupancy.vacant_between_occupied(), vec![huge.seat(5, 1)]);
+        assert_eq!(occupancy.duplicates(), vec![(huge.seat(5, 2), 2)]);
         Ok(())
     }
 }
@@ -1,7 +1,5 @@
-use anyhow::{anyhow, Result};
 use aoc_runner_derive::{aoc, aoc_generator};
 use std::collections::HashMap;
-use std::collections::HashSet;
 
 #[aoc_generator(day6)]
 pub fn input_generator(input: &str) -> Vec<Vec<String>> {
@@ -11,35 +9,272 @@ pub fn input_generator(input: &str) -> Vec<Vec<String>> {
         .collect()
 }
 
-#[aoc(day6, part1)]
-pub fn solve_part1(input: &[Vec<String>]) -> usize {
-    let mut sum = 0;
-    for group in input {
-        let mut set = HashSet::new();
-        for person in group {
-            person.chars().for_each(|c| {
-                set.insert(c);
-            });
+/// Assigns each distinct answer character a bit position, in order of first
+/// appearance, so any alphabet can be packed into an [`AnswerSet`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Alphabet {
+    chars: Vec<char>,
+    index: HashMap<char, usize>,
+}
+
+impl Alphabet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn index_of(&mut self, c: char) -> usize {
+        let chars = &mut self.chars;
+        *self.index.entry(c).or_insert_with(|| {
+            chars.push(c);
+            chars.len() - 1
+        })
+    }
+
+    pub fn char_at(&self, index: usize) -> Option<char> {
+        self.chars.get(index).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.chars.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chars.is_empty()
+    }
+}
+
+/// One person's answers as a bitset over an [`Alphabet`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AnswerSet(Vec<u64>);
+
+impl AnswerSet {
+    pub fn insert(&mut self, index: usize) {
+        if self.0.len() <= index / 64 {
+            self.0.resize(index / 64 + 1, 0);
         }
-        sum += set.len();
+        self.0[index / 64] |= 1 << (index % 64);
+    }
+
+    pub fn contains(&self, index: usize) -> bool {
+        self.0
+            .get(index / 64)
+            .is_some_and(|w| w & (1 << (index % 64)) != 0)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.iter().all(|&w| w == 0)
+    }
+
+    /// Indices of the answers in the set, in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.0.iter().enumerate().flat_map(|(i, &word)| {
+            let mut w = word;
+            std::iter::from_fn(move || {
+                if w == 0 {
+                    return None;
+                }
+                let bit = w.trailing_zeros() as usize;
+                w &= w - 1;
+                Some(i * 64 + bit)
+            })
+        })
     }
-    sum
 }
 
-#[aoc(day6, part2)]
-pub fn solve_part2(input: &[Vec<String>]) -> usize {
-    let mut sum = 0;
-    for group in input {
-        let mut map: HashMap<char, usize> = HashMap::new();
-        let group_size = group.len();
+/// Every group's answers packed into bitsets over a shared alphabet.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Groups {
+    pub alphabet: Alphabet,
+    pub groups: Vec<Vec<AnswerSet>>,
+}
+
+impl Groups {
+    /// Packs the output of [`input_generator`], skipping blank lines.
+    pub fn new(input: &[Vec<String>]) -> Self {
+        let mut alphabet = Alphabet::new();
+        let groups = input
+            .iter()
+            .map(|group| {
+                group
+                    .iter()
+                    .filter(|person| !person.is_empty())
+                    .map(|person| {
+                        let mut set = AnswerSet::default();
+                        person
+                            .chars()
+                            .for_each(|c| set.insert(alphabet.index_of(c)));
+                        set
+                    })
+                    .collect()
+            })
+            .collect();
+        Self { alphabet, groups }
+    }
+
+    /// How many members answered each question, indexed by alphabet position.
+    pub fn answer_counts(&self, group: &[AnswerSet]) -> Vec<usize> {
+        let mut counts = vec![0; self.alphabet.len()];
         for person in group {
-            person.chars().for_each(|c| {
-                map.entry(c).and_modify(|x| *x += 1).or_insert(1);
-            });
+            person.iter().for_each(|i| counts[i] += 1);
         }
-        sum += map.values().filter(|v| **v == group_size).count();
+        counts
+    }
+
+    /// Sums, over all groups, the questions answered by enough members of the group.
+    pub fn count_quorum(&self, quorum: Quorum) -> usize {
+        self.groups
+            .iter()
+            .map(|group| {
+                let required = quorum.required(group.len());
+                self.answer_counts(group)
+                    .into_iter()
+                    .filter(|&n| n >= required)
+                    .count()
+            })
+            .sum()
+    }
+}
+
+/// How one question was answered across all groups. Agreement is the
+/// fraction of a group's members giving the answer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuestionStats {
+    pub question: char,
+    /// Groups where anyone answered
+    pub groups: usize,
+    /// People answering, over all groups
+    pub people: usize,
+    /// Groups where everyone answered
+    pub unanimous: usize,
+    /// Index and agreement of the group agreeing most, first on ties
+    pub highest: (usize, f64),
+    /// Index and agreement of the answering group agreeing least, first on ties
+    pub lowest: (usize, f64),
+}
+
+impl QuestionStats {
+    /// Share of answering groups where everyone answered.
+    pub fn unanimity_rate(&self) -> f64 {
+        self.unanimous as f64 / self.groups as f64
+    }
+}
+
+impl Groups {
+    /// Statistics for every question anyone answered, in character order.
+    pub fn question_stats(&self) -> Vec<QuestionStats> {
+        let mut stats: Vec<Option<QuestionStats>> = vec![None; self.alphabet.len()];
+        for (g, group) in self.groups.iter().enumerate() {
+            for (i, n) in self.answer_counts(group).into_iter().enumerate() {
+                if n == 0 {
+                    continue;
+                }
+                let agreement = n as f64 / group.len() as f64;
+                let entry = stats[i].get_or_insert_with(|| QuestionStats {
+                    question: self.alphabet.char_at(i).expect("Alphabet index"),
+                    groups: 0,
+                    people: 0,
+                    unanimous: 0,
+                    highest: (g, agreement),
+                    lowest: (g, agreement),
+                });
+                entry.groups += 1;
+                entry.people += n;
+                if n == group.len() {
+                    entry.unanimous += 1;
+                }
+                if agreement > entry.highest.1 {
+                    entry.highest = (g, agreement);
+                }
+                if agreement < entry.lowest.1 {
+                    entry.lowest = (g, agreement);
+                }
+            }
+        }
+        let mut stats: Vec<QuestionStats> = stats.into_iter().flatten().collect();
+        stats.sort_by_key(|s| s.question);
+        stats
+    }
+}
+
+/// Renders question statistics as an aligned text table.
+pub fn stats_table(stats: &[QuestionStats]) -> String {
+    let mut out = format!(
+        "{:<8} {:>6} {:>6} {:>9} {:>14} {:>14}\n",
+        "question", "groups", "people", "unanimity", "highest", "lowest"
+    );
+    for s in stats {
+        let group = |(g, a): (usize, f64)| format!("#{} ({:.1}%)", g, a * 100.0);
+        out.push_str(&format!(
+            "{:<8} {:>6} {:>6} {:>8.1}% {:>14} {:>14}\n",
+            s.question,
+            s.groups,
+            s.people,
+            s.unanimity_rate() * 100.0,
+            group(s.highest),
+            group(s.lowest)
+        ));
+    }
+    out
+}
+
+/// Renders question statistics as CSV, with rates as fractions.
+pub fn stats_csv(stats: &[QuestionStats]) -> String {
+    let mut out = String::from(
+        "question,groups,people,unanimous,unanimity_rate,highest_group,highest_agreement,lowest_group,lowest_agreement\n",
+    );
+    for s in stats {
+        let question = match s.question {
+            ',' | '"' => format!("\"{}\"", s.question.to_string().replace('"', "\"\"")),
+            c => c.to_string(),
+        };
+        out.push_str(&format!(
+            "{},{},{},{},{:.4},{},{:.4},{},{:.4}\n",
+            question,
+            s.groups,
+            s.people,
+            s.unanimous,
+            s.unanimity_rate(),
+            s.highest.0,
+            s.highest.1,
+            s.lowest.0,
+            s.lowest.1
+        ));
     }
-    sum
+    out
+}
+
+/// How many members of a group must answer a question for it to count.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Quorum {
+    AtLeast(usize),
+    /// A fraction of the group's members, rounded up
+    Fraction(f64),
+}
+
+impl Quorum {
+    /// Members needed out of `members`, never less than one.
+    pub fn required(self, members: usize) -> usize {
+        let n = match self {
+            Quorum::AtLeast(n) => n,
+            // Allow for rounding error so that e.g. 0.7 of 10 needs 7, not 8
+            Quorum::Fraction(f) => (f * members as f64 - 1e-9).ceil().max(0.0) as usize,
+        };
+        n.max(1)
+    }
+}
+
+#[aoc(day6, part1)]
+pub fn solve_part1(input: &[Vec<String>]) -> usize {
+    Groups::new(input).count_quorum(Quorum::AtLeast(1))
+}
+
+#[aoc(day6, part2)]
+pub fn solve_part2(input: &[Vec<String>]) -> usize {
+    Groups::new(input).count_quorum(Quorum::Fraction(1.0))
 }
 
 #[cfg(test)]
@@ -87,4 +322,67 @@ b";
         let input = input_generator(ex);
         assert_eq!(solve_part2(&input), 6);
     }
+    #[test]
+    fn test_quorum() {
+        let ex = "abc
+ab
+aZ9
+
+xyz
+x
+
+Ä
+Äß";
+        let groups = Groups::new(&input_generator(ex));
+        assert_eq!(groups.alphabet.len(), 10);
+        assert_eq!(groups.count_quorum(Quorum::AtLeast(1)), 10);
+        assert_eq!(groups.count_quorum(Quorum::AtLeast(2)), 4);
+        assert_eq!(groups.count_quorum(Quorum::Fraction(0.5)), 7);
+        assert_eq!(groups.count_quorum(Quorum::Fraction(1.0)), 3);
+        assert_eq!(Quorum::Fraction(0.7).required(10), 7);
+        assert_eq!(Quorum::Fraction(0.0).required(10), 1);
+
+        let mut set = AnswerSet::default();
+        set.insert(3);
+        set.insert(130);
+        assert_eq!(set.iter().collect::<Vec<_>>(), vec![3, 130]);
+        assert_eq!(set.len(), 2);
+        assert!(set.contains(130) && !set.contains(64));
+    }
+    #[test]
+    fn test_question_stats() {
+        let ex = "abc
+
+a
+b
+c
+
+ab
+ac
+
+a
+a
+a
+a
+
+b";
+        let stats = Groups::new(&input_generator(ex)).question_stats();
+        let a = &stats[0];
+        assert_eq!(a.question, 'a');
+        assert_eq!((a.groups, a.people, a.unanimous), (4, 8, 3));
+        assert_eq!(a.highest, (0, 1.0));
+        assert_eq!(a.lowest.0, 1);
+        assert!((a.unanimity_rate() - 0.75).abs() < 1e-9);
+        assert_eq!(stats.len(), 3);
+
+        let table = stats_table(&stats);
+        assert_eq!(
+            table.lines().nth(1),
+            Some("a             4      8     75.0%    #0 (100.0%)     #1 (33.3%)")
+        );
+        assert_eq!(
+            stats_csv(&stats).lines().nth(1),
+            Some("a,4,8,3,0.7500,0,1.0000,1,0.3333")
+        );
+    }
 }
@@ -1,103 +1,589 @@
 use anyhow::{anyhow, Result};
 use aoc_runner_derive::{aoc, aoc_generator};
-use lazy_static::lazy_static;
-use regex::Regex;
+use std::cell::RefCell;
 use std::collections::{HashMap, HashSet};
+use std::fmt;
 
-#[aoc_generator(day7)]
-pub fn input_generator(input: &str) -> HashMap<String, Vec<(u32, String)>> {
-    input.lines().map(|l| parse_line(l)).collect()
+/// A bag colour and the (quantity, colour) of each bag directly inside it.
+pub type Rule = (String, Vec<(u32, String)>);
+
+/// Bag rules as a graph over interned colour IDs, with edges in both
+/// directions and memoized counts of the bags inside each bag.
+#[derive(Debug, Clone, Default)]
+pub struct BagGraph {
+    names: Vec<String>,
+    ids: HashMap<String, usize>,
+    /// (quantity, bag) directly inside each bag, in rule order
+    contains: Vec<Vec<(u32, usize)>>,
+    /// Bags directly holding each bag
+    held_by: Vec<Vec<usize>>,
+    totals: RefCell<Vec<Total>>,
 }
 
-fn parse_line(s: &str) -> (String, Vec<(u32, String)>) {
-    lazy_static! {
-        static ref subject_re: Regex = Regex::new(
-            // r"([a-z]+ [a-z]+) bags contain (([0-9]+) ([a-z]+ [a-z]+) bags(\.|,\s))+|(no other bags)"
-            r"([a-z]+ [a-z]+) bags contain"
-        )
-        .expect("Bad regex");
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Total {
+    Unknown,
+    /// Being computed, so meeting it again means a cycle
+    Visiting,
+    Known(u64),
+    /// Holds itself or more than `u64::MAX` bags
+    Unbounded,
+}
 
-        static ref edges_re: Regex = Regex::new(
-            // r"([a-z]+ [a-z]+) bags contain (([0-9]+) ([a-z]+ [a-z]+) bags(\.|,\s))+|(no other bags)"
-            r"([0-9]+) ([a-z]+ [a-z]+) bag"
-        )
-        .expect("Bad regex");
+impl BagGraph {
+    pub fn new() -> Self {
+        Self::default()
     }
 
-    let subject_name = subject_re
-        .captures(s)
-        .expect("No subject phrase")
-        .get(1)
-        .expect("No subject name")
-        .as_str()
-        .to_string();
+    /// Builds a graph from parsed rules, giving colours IDs in order of first mention.
+    pub fn from_rules<I>(rules: I) -> Self
+    where
+        I: IntoIterator<Item = Rule>,
+    {
+        let mut graph = Self::new();
+        for (bag, inside) in rules {
+            graph.add_rule(&bag, &inside);
+        }
+        graph
+    }
 
-    let edges = edges_re
-        .captures_iter(s)
-        .map(|x| (x[1].parse().expect("Bad int"), x[2].to_string()))
-        .collect();
+    fn intern(&mut self, colour: &str) -> usize {
+        if let Some(&id) = self.ids.get(colour) {
+            return id;
+        }
+        let id = self.names.len();
+        self.names.push(colour.to_string());
+        self.ids.insert(colour.to_string(), id);
+        self.contains.push(Vec::new());
+        self.held_by.push(Vec::new());
+        self.totals.get_mut().push(Total::Unknown);
+        id
+    }
 
-    (subject_name, edges)
-}
+    /// Adds the bags directly inside `bag`.
+    pub fn add_rule(&mut self, bag: &str, inside: &[(u32, String)]) {
+        let id = self.intern(bag);
+        for (n, colour) in inside {
+            let inner = self.intern(colour);
+            self.contains[id].push((*n, inner));
+            self.held_by[inner].push(id);
+        }
+        // Totals of this bag and everything holding it may have changed
+        self.totals
+            .get_mut()
+            .iter_mut()
+            .for_each(|t| *t = Total::Unknown);
+    }
 
-#[aoc(day7, part1)]
-pub fn solve_part1(input: &HashMap<String, Vec<(u32, String)>>) -> usize {
-    let mut to_check = Vec::new();
-    let mut seen = HashSet::new();
-    let mut sum = 0;
-    for (key, val) in input.iter() {
-        if val.iter().any(|x| x.1 == "shiny gold") {
-            if !to_check.contains(key) {
-                to_check.push(key.clone());
-                seen.insert(key.clone());
-                sum += 1;
+    pub fn id(&self, colour: &str) -> Option<usize> {
+        self.ids.get(colour).copied()
+    }
+
+    pub fn name(&self, id: usize) -> &str {
+        &self.names[id]
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// (quantity, colour ID) of the bags directly inside a bag.
+    pub fn contents(&self, id: usize) -> &[(u32, usize)] {
+        &self.contains[id]
+    }
+
+    /// Every colour that can eventually hold a `colour` bag, sorted by name.
+    pub fn holders(&self, colour: &str) -> Vec<&str> {
+        let start = match self.id(colour) {
+            Some(id) => id,
+            None => return Vec::new(),
+        };
+        let mut seen = vec![false; self.len()];
+        let mut to_check = vec![start];
+        while let Some(cur) = to_check.pop() {
+            for &outer in self.held_by[cur].iter() {
+                if !seen[outer] {
+                    seen[outer] = true;
+                    to_check.push(outer);
+                }
+            }
+        }
+        let mut out: Vec<&str> = (0..self.len())
+            .filter(|&id| seen[id] && id != start)
+            .map(|id| self.name(id))
+            .collect();
+        out.sort_unstable();
+        out
+    }
+
+    /// How many bags a `colour` bag holds in total. `None` for an unknown
+    /// colour, if the bag can eventually contain itself, or if the total
+    /// does not fit in a `u64`.
+    pub fn total_inside(&self, colour: &str) -> Option<u64> {
+        self.id(colour).and_then(|id| self.total_inside_id(id))
+    }
+
+    fn total_inside_id(&self, id: usize) -> Option<u64> {
+        let mut totals = self.totals.borrow_mut();
+        match totals[id] {
+            Total::Known(total) => return Some(total),
+            Total::Unbounded => return None,
+            Total::Unknown | Total::Visiting => {}
+        }
+        // Iterative DFS of (bag, next edge to follow, total so far) to avoid stack overflow
+        let mut stack: Vec<(usize, usize, u64)> = vec![(id, 0, 0)];
+        totals[id] = Total::Visiting;
+        while let Some(&mut (cur, ref mut edge, ref mut sum)) = stack.last_mut() {
+            let (n, inner) = match self.contains[cur].get(*edge) {
+                Some(&next) => next,
+                None => {
+                    totals[cur] = Total::Known(*sum);
+                    stack.pop();
+                    continue;
+                }
+            };
+            let inside = match totals[inner] {
+                Total::Known(total) => total
+                    .checked_add(1)
+                    .and_then(|t| t.checked_mul(n as u64))
+                    .and_then(|t| t.checked_add(*sum)),
+                Total::Unknown => {
+                    totals[inner] = Total::Visiting;
+                    stack.push((inner, 0, 0));
+                    continue;
+                }
+                Total::Visiting | Total::Unbounded => None,
+            };
+            match inside {
+                Some(total) => {
+                    *sum = total;
+                    *edge += 1;
+                }
+                None => {
+                    // Every bag on the stack holds the unbounded one
+                    for &(bag, _, _) in stack.iter() {
+                        totals[bag] = Total::Unbounded;
+                    }
+                    return None;
+                }
             }
         }
+        match totals[id] {
+            Total::Known(total) => Some(total),
+            _ => None,
+        }
     }
 
-    while to_check.len() > 0 {
-        let target = to_check.pop().expect("empty vec");
+    /// Cycles found by a depth-first search, each as the path from one of its
+    /// bags back to itself. At least one cycle is reported for every group of
+    /// bags that contain each other, but not every distinct cycle is listed.
+    pub fn cycles(&self) -> Vec<Vec<&str>> {
+        // 0: unvisited, 1: on the current path, 2: finished
+        let mut state = vec![0u8; self.len()];
+        let mut cycles = Vec::new();
+        for root in 0..self.len() {
+            if state[root] != 0 {
+                continue;
+            }
+            // Iterative DFS of (bag, next edge to follow) to avoid stack overflow
+            let mut path: Vec<(usize, usize)> = vec![(root, 0)];
+            state[root] = 1;
+            while let Some(&mut (cur, ref mut edge)) = path.last_mut() {
+                match self.contains[cur].get(*edge) {
+                    Some(&(_, next)) => {
+                        *edge += 1;
+                        match state[next] {
+                            0 => {
+                                state[next] = 1;
+                                path.push((next, 0));
+                            }
+                            1 => {
+                                let start = path.iter().position(|p| p.0 == next).unwrap_or(0);
+                                let mut cycle: Vec<&str> =
+                                    path[start..].iter().map(|p| self.name(p.0)).collect();
+                                cycle.push(self.name(next));
+                                cycles.push(cycle);
+                            }
+                            _ => {}
+                        }
+                    }
+                    None => {
+                        state[cur] = 2;
+                        path.pop();
+                    }
+                }
+            }
+        }
+        cycles
+    }
 
-        for (key, val) in input.iter() {
-            // println!("{}: {}, {:?} - {:?}", target, key, val, seen);
-            if val.iter().any(|x| x.1 == target) {
-                if (!to_check.contains(key)) && (!seen.contains(key)) {
-                    to_check.push(key.clone());
-                    seen.insert(key.clone());
-                    sum += 1;
+    /// Colour IDs of a bag and everything it can eventually hold.
+    fn reachable_from(&self, start: usize) -> Vec<bool> {
+        let mut seen = vec![false; self.len()];
+        seen[start] = true;
+        let mut to_check = vec![start];
+        while let Some(cur) = to_check.pop() {
+            for &(_, inner) in self.contains[cur].iter() {
+                if !seen[inner] {
+                    seen[inner] = true;
+                    to_check.push(inner);
                 }
             }
         }
+        seen
     }
 
-    sum
+    /// Exports the graph in Graphviz DOT format, with an edge from each bag
+    /// to each bag it directly holds labelled with the quantity. If
+    /// `highlight` names a colour, that bag and everything it can eventually
+    /// hold are drawn in red.
+    pub fn to_dot(&self, highlight: Option<&str>) -> String {
+        let lit = match highlight.and_then(|c| self.id(c)) {
+            Some(id) => self.reachable_from(id),
+            None => vec![false; self.len()],
+        };
+        let quote = |id: usize| format!("\"{}\"", self.name(id).replace('"', "\\\""));
+
+        let mut out = String::from("digraph bags {\n");
+        for (id, &highlighted) in lit.iter().enumerate() {
+            out.push_str(&format!("    {}", quote(id)));
+            if highlighted {
+                out.push_str(" [color=red, fontcolor=red]");
+            }
+            out.push_str(";\n");
+        }
+        for (outer, inside) in self.contains.iter().enumerate() {
+            for &(n, inner) in inside.iter() {
+                out.push_str(&format!(
+                    "    {} -> {} [label=\"{}\"",
+                    quote(outer),
+                    quote(inner),
+                    n
+                ));
+                if lit[outer] {
+                    out.push_str(", color=red");
+                }
+                out.push_str("];\n");
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    /// Every way a `from` bag can hold a `to` bag, ordered as the rules list
+    /// their contents. Paths never repeat a colour, so cycles are not followed.
+    pub fn paths(&self, from: &str, to: &str) -> Vec<BagPath<'_>> {
+        let (start, end) = match (self.id(from), self.id(to)) {
+            (Some(start), Some(end)) => (start, end),
+            _ => return Vec::new(),
+        };
+        let mut found = Vec::new();
+        let mut on_path = vec![false; self.len()];
+        let mut path = BagPath {
+            colours: vec![self.name(start)],
+            quantities: Vec::new(),
+        };
+        on_path[start] = true;
+        // Iterative DFS of (bag, next edge to follow) to avoid stack overflow
+        let mut stack = vec![(start, 0)];
+        while let Some(top) = stack.last_mut() {
+            let (cur, edge) = *top;
+            top.1 += 1;
+            match self.contains[cur].get(edge) {
+                Some(&(_, inner)) if on_path[inner] => {}
+                Some(&(n, inner)) => {
+                    path.colours.push(self.name(inner));
+                    path.quantities.push(n);
+                    if inner == end {
+                        found.push(path.clone());
+                        path.colours.pop();
+                        path.quantities.pop();
+                    } else {
+                        on_path[inner] = true;
+                        stack.push((inner, 0));
+                    }
+                }
+                None => {
+                    on_path[cur] = false;
+                    stack.pop();
+                    if !stack.is_empty() {
+                        path.colours.pop();
+                        path.quantities.pop();
+                    }
+                }
+            }
+        }
+        found
+    }
 }
 
-#[aoc(day7, part2)]
-pub fn solve_part2(input: &HashMap<String, Vec<(u32, String)>>) -> u32 {
-    // DFS from shiny gold
-    let mut to_check = Vec::new();
-    // let mut seen = HashSet::new();
-    let mut sum = 0;
+/// A chain of bags each directly inside the one before it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BagPath<'a> {
+    pub colours: Vec<&'a str>,
+    /// `quantities[i]` is how many `colours[i + 1]` bags one `colours[i]` bag holds
+    pub quantities: Vec<u32>,
+}
 
-    let start = input.get("shiny gold").expect("No shiny gold");
-    let i = 1;
-    start.iter().for_each(|x| {
-        sum += i * x.0;
-        to_check.push((x.0, x.1.clone()));
-    });
-    // seen.insert("shiny gold".to_string());
+impl BagPath<'_> {
+    /// How many of the last bag one of the first bag holds along this path,
+    /// or `None` if that does not fit in a `u64`.
+    pub fn total(&self) -> Option<u64> {
+        self.quantities
+            .iter()
+            .try_fold(1u64, |total, &n| total.checked_mul(n as u64))
+    }
+}
 
-    // Iterative DFS - avoid stack overflow
-    while to_check.len() > 0 {
-        let cur = to_check.pop().expect("Empty vec");
-        input.get(&cur.1).expect("Bad Key").iter().for_each(|x| {
-            sum += x.0 * cur.0;
-            to_check.push((x.0 * cur.0, x.1.clone()));
-        });
+impl fmt::Display for BagPath<'_> {
+    /// Formats as `shiny gold -1-> dark olive -3-> faded blue (3)`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.colours[0])?;
+        for (n, colour) in self.quantities.iter().zip(&self.colours[1..]) {
+            write!(f, " -{}-> {}", n, colour)?;
+        }
+        match self.total() {
+            Some(total) => write!(f, " ({})", total),
+            None => write!(f, " (overflow)"),
+        }
     }
+}
+
+/// A problem with a set of bag rules.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RuleError {
+    /// A bag that can eventually contain itself, as the path from the bag back to itself
+    Cycle(Vec<String>),
+    /// A rule mentions a colour that has no rule of its own
+    Undefined { bag: String, colour: String },
+    /// More than one rule for the same colour
+    Duplicate(String),
+}
+
+impl fmt::Display for RuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuleError::Cycle(path) => write!(f, "Cycle: {}", path.join(" -> ")),
+            RuleError::Undefined { bag, colour } => {
+                write!(f, "{} bags contain undefined colour {}", bag, colour)
+            }
+            RuleError::Duplicate(bag) => write!(f, "{} bags are defined more than once", bag),
+        }
+    }
+}
+
+impl std::error::Error for RuleError {}
+
+/// Checks parsed rules for duplicate definitions, undefined colours and
+/// cycles, reporting all problems found.
+pub fn validate_rules(rules: &[Rule]) -> Result<(), Vec<RuleError>> {
+    let mut errors = Vec::new();
+
+    let mut defined = HashSet::with_capacity(rules.len());
+    for (bag, _) in rules {
+        if !defined.insert(bag.as_str()) && !errors.contains(&RuleError::Duplicate(bag.clone())) {
+            errors.push(RuleError::Duplicate(bag.clone()));
+        }
+    }
+    for (bag, inside) in rules {
+        for (_, colour) in inside {
+            if !defined.contains(colour.as_str()) {
+                errors.push(RuleError::Undefined {
+                    bag: bag.clone(),
+                    colour: colour.clone(),
+                });
+            }
+        }
+    }
+
+    let graph = BagGraph::from_rules(rules.iter().cloned());
+    errors.extend(
+        graph
+            .cycles()
+            .into_iter()
+            .map(|c| RuleError::Cycle(c.into_iter().map(|s| s.to_string()).collect())),
+    );
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+impl BagGraph {
+    /// Builds a graph only if the rules pass [`validate_rules`].
+    pub fn try_from_rules(rules: Vec<Rule>) -> Result<Self, Vec<RuleError>> {
+        validate_rules(&rules)?;
+        Ok(Self::from_rules(rules))
+    }
+
+    /// Writes the graph back out as one canonical rule sentence per colour,
+    /// in order of first mention. Colours without rules of their own are
+    /// written as holding no other bags.
+    pub fn to_rules(&self) -> String {
+        let bags = |n: u32| if n == 1 { "bag" } else { "bags" };
+        let mut out = String::new();
+        for (id, inside) in self.contains.iter().enumerate() {
+            let contents = if inside.is_empty() {
+                "no other bags".to_string()
+            } else {
+                let items: Vec<String> = inside
+                    .iter()
+                    .map(|&(n, inner)| format!("{} {} {}", n, self.name(inner), bags(n)))
+                    .collect();
+                items.join(", ")
+            };
+            out.push_str(&format!("{} bags contain {}.\n", self.name(id), contents));
+        }
+        out
+    }
+}
+
+/// What is wrong with a single rule sentence.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RuleSyntax {
+    MissingPeriod,
+    MissingContain,
+    /// Not one or more words of letters and inner hyphens
+    BadColour(String),
+    /// A bag phrase not ending in `bag` or `bags`
+    MissingBag(String),
+    /// A content item not starting with a positive count
+    BadQuantity(String),
+}
+
+impl fmt::Display for RuleSyntax {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuleSyntax::MissingPeriod => write!(f, "rule does not end with '.'"),
+            RuleSyntax::MissingContain => write!(f, "no 'contain' in rule"),
+            RuleSyntax::BadColour(colour) => write!(f, "bad colour {:?}", colour),
+            RuleSyntax::MissingBag(phrase) => write!(f, "expected bag or bags in {:?}", phrase),
+            RuleSyntax::BadQuantity(item) => write!(f, "expected a positive count in {:?}", item),
+        }
+    }
+}
+
+impl std::error::Error for RuleSyntax {}
 
-    sum
+/// A syntax error on a 1-indexed line of the rules.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,
+    pub syntax: RuleSyntax,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Line {}: {}", self.line, self.syntax)
+    }
+}
+
+impl std::error::Error for ParseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.syntax)
+    }
+}
+
+/// Parses one rule per non-blank line, stopping at the first bad line.
+pub fn parse_rules(input: &str) -> Result<Vec<Rule>, ParseError> {
+    input
+        .lines()
+        .enumerate()
+        .filter(|(_, l)| !l.trim().is_empty())
+        .map(|(i, l)| {
+            parse_line(l).map_err(|syntax| ParseError {
+                line: i + 1,
+                syntax,
+            })
+        })
+        .collect()
+}
+
+#[aoc_generator(day7)]
+pub fn input_generator(input: &str) -> Result<BagGraph> {
+    BagGraph::try_from_rules(parse_rules(input)?).map_err(|errors| {
+        let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        anyhow!(errors.join("; "))
+    })
+}
+
+/// Parses a sentence like `pale cyan bags contain 1 dark-red bag, 2 navy bags.`,
+/// where colours may be any number of words and runs of whitespace count as one space.
+pub fn parse_line(s: &str) -> Result<Rule, RuleSyntax> {
+    let words: Vec<&str> = s.split_whitespace().collect();
+    let s = words.join(" ");
+    let s = s.strip_suffix('.').ok_or(RuleSyntax::MissingPeriod)?;
+    let (subject, contents) = split_once_word(s, "contain").ok_or(RuleSyntax::MissingContain)?;
+
+    let bag = bag_colour(subject)?;
+    if contents == "no other bags" {
+        return Ok((bag, Vec::new()));
+    }
+    let edges = contents
+        .split(',')
+        .map(|item| {
+            let item = item.trim();
+            let (count, phrase) = item
+                .split_once(' ')
+                .ok_or_else(|| RuleSyntax::BadQuantity(item.to_string()))?;
+            match count.parse::<u32>() {
+                Ok(n) if n > 0 => Ok((n, bag_colour(phrase)?)),
+                _ => Err(RuleSyntax::BadQuantity(item.to_string())),
+            }
+        })
+        .collect::<Result<_, _>>()?;
+    Ok((bag, edges))
+}
+
+// Splits around the first occurrence of `word` as a whole word
+fn split_once_word<'a>(s: &'a str, word: &str) -> Option<(&'a str, &'a str)> {
+    let mut start = 0;
+    for w in s.split(' ') {
+        if w == word {
+            let rest = s.get(start + w.len() + 1..).unwrap_or("");
+            return Some((s[..start].trim_end(), rest));
+        }
+        start += w.len() + 1;
+    }
+    None
+}
+
+// The colour of a phrase like `dark-red bags` or `navy bag`
+fn bag_colour(phrase: &str) -> Result<String, RuleSyntax> {
+    let colour = match phrase.rsplit_once(' ') {
+        Some((colour, "bag")) | Some((colour, "bags")) => colour,
+        _ => return Err(RuleSyntax::MissingBag(phrase.to_string())),
+    };
+    let valid_word = |w: &str| {
+        !w.is_empty()
+            && !w.starts_with('-')
+            && !w.ends_with('-')
+            && !w.contains("--")
+            && w.chars().all(|c| c.is_alphabetic() || c == '-')
+    };
+    if colour.split(' ').all(valid_word) {
+        Ok(colour.to_string())
+    } else {
+        Err(RuleSyntax::BadColour(colour.to_string()))
+    }
+}
+
+#[aoc(day7, part1)]
+pub fn solve_part1(input: &BagGraph) -> usize {
+    input.holders("shiny gold").len()
+}
+
+#[aoc(day7, part2)]
+pub fn solve_part2(input: &BagGraph) -> Result<u64> {
+    input
+        .total_inside("shiny gold")
+        .ok_or_else(|| anyhow!("No shiny gold, or it contains itself"))
 }
 
 #[cfg(test)]
@@ -106,9 +592,9 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_parse() {
+    fn test_parse() -> Result<()> {
         let ex = "light red bags contain 1 bright white bag, 2 muted yellow bags.";
-        let input = parse_line(ex);
+        let input = parse_line(ex)?;
         assert_eq!(
             input,
             (
@@ -119,15 +605,81 @@ mod tests {
                 ]
             )
         );
+        Ok(())
     }
     #[test]
-    fn test_parse_empty() {
+    fn test_parse_empty() -> Result<()> {
         let ex = "faded blue bags contain no other bags.";
-        let input = parse_line(ex);
+        let input = parse_line(ex)?;
         assert_eq!(input, ("faded blue".to_string(), vec![]));
+        Ok(())
+    }
+    #[test]
+    fn test_parse_free_form() -> Result<()> {
+        let ex = "pale  sky-blue bags contain 1 very dark red bag, 12 navy bags.
+
+navy bag contain no other bags.
+very dark red bags contain 2 navy bag.";
+        let rules = parse_rules(ex)?;
+        assert_eq!(
+            rules[0],
+            (
+                "pale sky-blue".to_string(),
+                vec![(1, "very dark red".to_string()), (12, "navy".to_string())]
+            )
+        );
+        assert_eq!(
+            rules[2],
+            ("very dark red".to_string(), vec![(2, "navy".to_string())])
+        );
+
+        let graph = input_generator(ex)?;
+        assert_eq!(graph.total_inside("pale sky-blue"), Some(15));
+        let canonical = graph.to_rules();
+        assert_eq!(
+            canonical,
+            "pale sky-blue bags contain 1 very dark red bag, 12 navy bags.
+very dark red bags contain 2 navy bags.
+navy bags contain no other bags.
+"
+        );
+        assert_eq!(input_generator(&canonical)?.to_rules(), canonical);
+        Ok(())
+    }
+    #[test]
+    fn test_parse_errors() {
+        let error = |ex: &str| parse_rules(ex).expect_err("Rules should be invalid");
+        let ok = "navy bags contain no other bags.\n";
+        assert_eq!(
+            error(&format!("{}\nred bags contain 1 navy bags", ok)),
+            ParseError {
+                line: 3,
+                syntax: RuleSyntax::MissingPeriod
+            }
+        );
+        assert_eq!(
+            error("red bags hold 1 navy bag.").syntax,
+            RuleSyntax::MissingContain
+        );
+        assert_eq!(
+            error("red-- bags contain no other bags.").syntax,
+            RuleSyntax::BadColour("red--".to_string())
+        );
+        assert_eq!(
+            error("red bags contain 1 navy box.").syntax,
+            RuleSyntax::MissingBag("navy box".to_string())
+        );
+        assert_eq!(
+            error("red bags contain 0 navy bags.").syntax,
+            RuleSyntax::BadQuantity("0 navy bags".to_string())
+        );
+        assert_eq!(
+            error(&format!("{}red bags contain some navy bags.", ok)).to_string(),
+            "Line 2: expected a positive count in \"some navy bags\""
+        );
     }
     #[test]
-    fn test1() {
+    fn test1() -> Result<()> {
         let ex = "light red bags contain 1 bright white bag, 2 muted yellow bags.
 dark orange bags contain 3 bright white bags, 4 muted yellow bags.
 bright white bags contain 1 shiny gold bag.
@@ -137,12 +689,13 @@ dark olive bags contain 3 faded blue bags, 4 dotted black bags.
 vibrant plum bags contain 5 faded blue bags, 6 dotted black bags.
 faded blue bags contain no other bags.
 dotted black bags contain no other bags.";
-        let input = input_generator(ex);
+        let input = input_generator(ex)?;
         let sol = solve_part1(&input);
         assert_eq!(sol, 4);
+        Ok(())
     }
     #[test]
-    fn test2() {
+    fn test2() -> Result<()> {
         let ex = "light red bags contain 1 bright white bag, 2 muted yellow bags.
 dark orange bags contain 3 bright white bags, 4 muted yellow bags.
 bright white bags contain 1 shiny gold bag.
@@ -152,12 +705,13 @@ dark olive bags contain 3 faded blue bags, 4 dotted black bags.
 vibrant plum bags contain 5 faded blue bags, 6 dotted black bags.
 faded blue bags contain no other bags.
 dotted black bags contain no other bags.";
-        let input = input_generator(ex);
-        let sol = solve_part2(&input);
+        let input = input_generator(ex)?;
+        let sol = solve_part2(&input)?;
         assert_eq!(sol, 32);
+        Ok(())
     }
     #[test]
-    fn test3() {
+    fn test3() -> Result<()> {
         let ex = "shiny gold bags contain 2 dark red bags.
 dark red bags contain 2 dark orange bags.
 dark orange bags contain 2 dark yellow bags.
@@ -165,8 +719,121 @@ dark yellow bags contain 2 dark green bags.
 dark green bags contain 2 dark blue bags.
 dark blue bags contain 2 dark violet bags.
 dark violet bags contain no other bags.";
-        let input = input_generator(ex);
-        let sol = solve_part2(&input);
+        let input = input_generator(ex)?;
+        let sol = solve_part2(&input)?;
         assert_eq!(sol, 126);
+        Ok(())
+    }
+    #[test]
+    fn test_graph_queries() -> Result<()> {
+        let ex = "light red bags contain 1 bright white bag, 2 muted yellow bags.
+dark orange bags contain 3 bright white bags, 4 muted yellow bags.
+bright white bags contain 1 shiny gold bag.
+muted yellow bags contain 2 shiny gold bags, 9 faded blue bags.
+shiny gold bags contain 1 dark olive bag, 2 vibrant plum bags.
+dark olive bags contain 3 faded blue bags, 4 dotted black bags.
+vibrant plum bags contain 5 faded blue bags, 6 dotted black bags.
+faded blue bags contain no other bags.
+dotted black bags contain no other bags.";
+        let graph = input_generator(ex)?;
+        assert_eq!(graph.len(), 9);
+        assert_eq!(graph.holders("dark olive").len(), 5);
+        assert_eq!(
+            graph.holders("bright white"),
+            vec!["dark orange", "light red"]
+        );
+        assert_eq!(graph.holders("no such colour"), Vec::<&str>::new());
+        assert_eq!(graph.total_inside("dark olive"), Some(7));
+        assert_eq!(graph.total_inside("light red"), Some(186));
+        assert_eq!(graph.total_inside("no such colour"), None);
+        Ok(())
+    }
+    #[test]
+    fn test_validate() {
+        let ex = "shiny gold bags contain 2 dark red bags.
+dark red bags contain 1 dark blue bag, 3 pale cyan bags.
+dark blue bags contain 1 shiny gold bag.
+faded blue bags contain no other bags.
+faded blue bags contain 1 dark red bag.";
+        let rules = parse_rules(ex).expect("Rules should parse");
+        let errors = validate_rules(&rules).expect_err("Rules should be invalid");
+        assert_eq!(
+            errors,
+            vec![
+                RuleError::Duplicate("faded blue".to_string()),
+                RuleError::Undefined {
+                    bag: "dark red".to_string(),
+                    colour: "pale cyan".to_string()
+                },
+                RuleError::Cycle(vec![
+                    "shiny gold".to_string(),
+                    "dark red".to_string(),
+                    "dark blue".to_string(),
+                    "shiny gold".to_string()
+                ]),
+            ]
+        );
+        assert_eq!(
+            errors[2].to_string(),
+            "Cycle: shiny gold -> dark red -> dark blue -> shiny gold"
+        );
+        assert!(input_generator(ex).is_err());
+
+        // Queries on an unvalidated cyclic graph fail instead of looping forever
+        let graph = BagGraph::from_rules(rules);
+        assert_eq!(graph.total_inside("faded blue"), None);
+        assert_eq!(graph.total_inside("pale cyan"), Some(0));
+    }
+    #[test]
+    fn test_deep_totals() {
+        // Deep enough to overflow the stack if counted recursively
+        let chain = (0..20_000).map(|i| (format!("c{}", i), vec![(1, format!("c{}", i + 1))]));
+        let graph = BagGraph::from_rules(chain);
+        assert_eq!(graph.total_inside("c0"), Some(20_000));
+        assert_eq!(graph.total_inside("c19999"), Some(1));
+        assert_eq!(graph.paths("c0", "c20000")[0].colours.len(), 20_001);
+
+        let chain = (0..30).map(|i| (format!("c{}", i), vec![(1000, format!("c{}", i + 1))]));
+        let graph = BagGraph::from_rules(chain);
+        assert_eq!(graph.total_inside("c0"), None);
+        assert_eq!(graph.total_inside("c1"), None);
+        assert_eq!(graph.total_inside("c25"), Some(1_001_001_001_001_000));
+        let paths = graph.paths("c0", "c30");
+        assert_eq!(paths.len(), 1);
+        assert_eq!(paths[0].total(), None);
+        assert!(paths[0].to_string().ends_with("-1000-> c30 (overflow)"));
+        assert_eq!(graph.paths("c25", "c30")[0].total(), Some(1000u64.pow(5)));
+    }
+    #[test]
+    fn test_export() -> Result<()> {
+        let ex = "light red bags contain 1 bright white bag, 2 muted yellow bags.
+bright white bags contain 1 shiny gold bag.
+muted yellow bags contain 2 shiny gold bags, 9 faded blue bags.
+shiny gold bags contain 3 faded blue bags.
+faded blue bags contain no other bags.";
+        let graph = input_generator(ex)?;
+
+        let paths = graph.paths("light red", "faded blue");
+        let shown: Vec<String> = paths.iter().map(|p| p.to_string()).collect();
+        assert_eq!(
+            shown,
+            vec![
+                "light red -1-> bright white -1-> shiny gold -3-> faded blue (3)",
+                "light red -2-> muted yellow -2-> shiny gold -3-> faded blue (12)",
+                "light red -2-> muted yellow -9-> faded blue (18)",
+            ]
+        );
+        // Summing over paths gives the number of that colour inside
+        assert_eq!(paths.iter().filter_map(|p| p.total()).sum::<u64>(), 33);
+        assert!(graph.paths("faded blue", "light red").is_empty());
+
+        let dot = graph.to_dot(Some("muted yellow"));
+        assert!(dot.starts_with("digraph bags {\n    \"light red\";\n"));
+        assert!(dot.contains("    \"muted yellow\" [color=red, fontcolor=red];\n"));
+        assert!(dot.contains("    \"bright white\";\n"));
+        assert!(dot.contains("    \"muted yellow\" -> \"faded blue\" [label=\"9\", color=red];\n"));
+        assert!(dot.contains("    \"light red\" -> \"bright white\" [label=\"1\"];\n"));
+        assert!(dot.ends_with("}\n"));
+        Ok(())
     }
 }
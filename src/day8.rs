@@ -1,156 +1,1101 @@
 use anyhow::{anyhow, Result};
 use aoc_runner_derive::{aoc, aoc_generator};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::io::{self, BufRead, Write};
 use std::str::FromStr;
 
-#[derive(Debug, Copy, Clone)]
+/// The accumulator, plus four general purpose registers.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Register {
+    Acc,
+    A,
+    B,
+    C,
+    D,
+}
+
+impl FromStr for Register {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "acc" => Ok(Self::Acc),
+            "a" => Ok(Self::A),
+            "b" => Ok(Self::B),
+            "c" => Ok(Self::C),
+            "d" => Ok(Self::D),
+            _ => Err(anyhow!("Unknown register: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Acc => "acc",
+            Self::A => "a",
+            Self::B => "b",
+            Self::C => "c",
+            Self::D => "d",
+        })
+    }
+}
+
+/// An opcode, with its register for those that use one. Every instruction
+/// takes one value, which for the jumps is an offset from its own pc.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum InstructionType {
     Nop,
     Acc,
     Jmp,
+    /// Sets the register to the value
+    Set(Register),
+    /// Adds the value to the register
+    Add(Register),
+    /// Multiplies the register by the value
+    Mul(Register),
+    /// Jumps if the register is zero
+    Jz(Register),
+    /// Jumps if the register is not zero
+    Jnz(Register),
 }
 
-#[derive(Debug, Copy, Clone)]
-pub struct Instruction {
-    pub instruction_type: InstructionType,
-    pub val: i32,
+impl InstructionType {
+    /// The opcode without any register, e.g. `jz`.
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Self::Nop => "nop",
+            Self::Acc => "acc",
+            Self::Jmp => "jmp",
+            Self::Set(_) => "set",
+            Self::Add(_) => "add",
+            Self::Mul(_) => "mul",
+            Self::Jz(_) => "jz",
+            Self::Jnz(_) => "jnz",
+        }
+    }
+
+    pub fn register(&self) -> Option<Register> {
+        match *self {
+            Self::Nop | Self::Acc | Self::Jmp => None,
+            Self::Set(r) | Self::Add(r) | Self::Mul(r) | Self::Jz(r) | Self::Jnz(r) => Some(r),
+        }
+    }
+
+    /// Whether the value is an offset to jump by.
+    pub fn is_jump(&self) -> bool {
+        matches!(self, Self::Jmp | Self::Jz(_) | Self::Jnz(_))
+    }
+
+    /// Whether control flow depends on the registers.
+    pub fn is_conditional(&self) -> bool {
+        matches!(self, Self::Jz(_) | Self::Jnz(_))
+    }
 }
 
 impl FromStr for InstructionType {
     type Err = anyhow::Error;
 
+    /// Parses an opcode, followed by its register if it has one, e.g. `jz a`.
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "nop" => Ok(Self::Nop),
-            "acc" => Ok(Self::Acc),
-            "jmp" => Ok(Self::Jmp),
+        let words: Vec<&str> = s.split_whitespace().collect();
+        let register = || -> Result<Register> {
+            match words[1..] {
+                [r] => r.parse(),
+                _ => Err(anyhow!("Expected one register: {}", s)),
+            }
+        };
+        match words.first().copied() {
+            Some("nop") if words.len() == 1 => Ok(Self::Nop),
+            Some("acc") if words.len() == 1 => Ok(Self::Acc),
+            Some("jmp") if words.len() == 1 => Ok(Self::Jmp),
+            Some("set") => Ok(Self::Set(register()?)),
+            Some("add") => Ok(Self::Add(register()?)),
+            Some("mul") => Ok(Self::Mul(register()?)),
+            Some("jz") => Ok(Self::Jz(register()?)),
+            Some("jnz") => Ok(Self::Jnz(register()?)),
             _ => Err(anyhow!("Unknown instruction type: {}", s)),
         }
     }
 }
 
+impl fmt::Display for InstructionType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.register() {
+            Some(r) => write!(f, "{} {}", self.mnemonic(), r),
+            None => f.write_str(self.mnemonic()),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Instruction {
+    pub instruction_type: InstructionType,
+    pub val: i32,
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {:+}", self.instruction_type, self.val)
+    }
+}
+
 impl FromStr for Instruction {
     type Err = anyhow::Error;
 
+    /// Parses one instruction with a numeric value. Use [`assemble`] for labels.
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut split = s.split(' ');
-        let ins = InstructionType::from_str(split.next().ok_or(anyhow!("No instruction: {}", s))?)?;
-        let val = split
-            .next()
-            .ok_or(anyhow!("No value part: {}", s))?
-            .parse()?;
-        Ok(Self {
-            instruction_type: ins,
-            val,
+        parse_instruction(s, &|_| None).map_err(|e| anyhow!("{}: {}", e, s))
+    }
+}
+
+/// What is wrong with a line of assembly.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AsmSyntax {
+    UnknownOp(String),
+    BadRegister(String),
+    MissingOperand,
+    ExtraOperand(String),
+    /// Neither a number nor, for jumps, a label
+    BadOperand(String),
+    UnknownLabel(String),
+    BadLabel(String),
+    DuplicateLabel(String),
+}
+
+impl fmt::Display for AsmSyntax {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AsmSyntax::UnknownOp(op) => write!(f, "unknown instruction {:?}", op),
+            AsmSyntax::BadRegister(r) => write!(f, "unknown register {:?}", r),
+            AsmSyntax::MissingOperand => write!(f, "missing operand"),
+            AsmSyntax::ExtraOperand(o) => write!(f, "unexpected operand {:?}", o),
+            AsmSyntax::BadOperand(o) => write!(f, "bad operand {:?}", o),
+            AsmSyntax::UnknownLabel(l) => write!(f, "unknown label {:?}", l),
+            AsmSyntax::BadLabel(l) => write!(f, "bad label name {:?}", l),
+            AsmSyntax::DuplicateLabel(l) => write!(f, "label {:?} defined twice", l),
+        }
+    }
+}
+
+impl std::error::Error for AsmSyntax {}
+
+/// A syntax error on a 1-indexed line of assembly.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub line: usize,
+    pub syntax: AsmSyntax,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Line {}: {}", self.line, self.syntax)
+    }
+}
+
+impl std::error::Error for AsmError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.syntax)
+    }
+}
+
+fn is_label(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// Parses an instruction, with `offset` giving the jump offset to a label
+fn parse_instruction(
+    s: &str,
+    offset: &dyn Fn(&str) -> Option<i32>,
+) -> Result<Instruction, AsmSyntax> {
+    let mut words = s.split_whitespace();
+    let op = words.next().ok_or(AsmSyntax::MissingOperand)?;
+    let mut register = || -> Result<Register, AsmSyntax> {
+        let r = words.next().ok_or(AsmSyntax::MissingOperand)?;
+        r.parse().map_err(|_| AsmSyntax::BadRegister(r.to_string()))
+    };
+    let instruction_type = match op {
+        "nop" => InstructionType::Nop,
+        "acc" => InstructionType::Acc,
+        "jmp" => InstructionType::Jmp,
+        "set" => InstructionType::Set(register()?),
+        "add" => InstructionType::Add(register()?),
+        "mul" => InstructionType::Mul(register()?),
+        "jz" => InstructionType::Jz(register()?),
+        "jnz" => InstructionType::Jnz(register()?),
+        _ => return Err(AsmSyntax::UnknownOp(op.to_string())),
+    };
+    let operand = words.next().ok_or(AsmSyntax::MissingOperand)?;
+    if let Some(extra) = words.next() {
+        return Err(AsmSyntax::ExtraOperand(extra.to_string()));
+    }
+    // A nop's value is a jump offset in waiting, so it may name a label too
+    let val = match operand.parse() {
+        Ok(val) => val,
+        Err(_) if !is_label(operand) => return Err(AsmSyntax::BadOperand(operand.to_string())),
+        Err(_) if instruction_type.is_jump() || instruction_type == InstructionType::Nop => {
+            offset(operand).ok_or_else(|| AsmSyntax::UnknownLabel(operand.to_string()))?
+        }
+        Err(_) => return Err(AsmSyntax::BadOperand(operand.to_string())),
+    };
+    Ok(Instruction {
+        instruction_type,
+        val,
+    })
+}
+
+/// Assembles one instruction per line, resolving labels to relative offsets.
+///
+/// A line may start with `label:`, which names the next instruction (or the
+/// end of the program), and anything after `;` is a comment.
+pub fn assemble(source: &str) -> Result<Vec<Instruction>, AsmError> {
+    let mut labels = HashMap::new();
+    let mut lines = Vec::new();
+    for (i, raw) in source.lines().enumerate() {
+        let mut text = raw.split(';').next().unwrap_or("").trim();
+        if let Some((label, rest)) = text.split_once(':') {
+            let label = label.trim();
+            let error = |syntax| AsmError {
+                line: i + 1,
+                syntax,
+            };
+            if !is_label(label) {
+                return Err(error(AsmSyntax::BadLabel(label.to_string())));
+            }
+            if labels.insert(label, lines.len()).is_some() {
+                return Err(error(AsmSyntax::DuplicateLabel(label.to_string())));
+            }
+            text = rest.trim();
+        }
+        if !text.is_empty() {
+            lines.push((i + 1, text));
+        }
+    }
+    lines
+        .iter()
+        .enumerate()
+        .map(|(pc, &(line, text))| {
+            let offset = |label: &str| labels.get(label).map(|&to| to as i32 - pc as i32);
+            parse_instruction(text, &offset).map_err(|syntax| AsmError { line, syntax })
         })
+        .collect()
+}
+
+/// Prints a program as assembly, replacing jump offsets that land in the
+/// program with labels `L<pc>` naming the target. [`assemble`] reads it back.
+pub fn disassemble(program: &[Instruction]) -> String {
+    let len = program.len();
+    let target = |pc: usize, ins: &Instruction| {
+        let to = pc as i64 + ins.val as i64;
+        if ins.instruction_type.is_jump() && (0..=len as i64).contains(&to) {
+            Some(to as usize)
+        } else {
+            None
+        }
+    };
+    let mut labelled = vec![false; len + 1];
+    for (pc, ins) in program.iter().enumerate() {
+        if let Some(to) = target(pc, ins) {
+            labelled[to] = true;
+        }
+    }
+    let mut out = String::new();
+    for (pc, ins) in program.iter().enumerate() {
+        if labelled[pc] {
+            out.push_str(&format!("L{}:\n", pc));
+        }
+        match target(pc, ins) {
+            Some(to) => out.push_str(&format!("    {} L{}\n", ins.instruction_type, to)),
+            None => out.push_str(&format!("    {}\n", ins)),
+        }
+    }
+    if labelled[len] {
+        out.push_str(&format!("L{}:\n", len));
+    }
+    out
+}
+
+/// Receives each instruction the VM runs. [`NoTrace`] ignores them, and
+/// [`Trace`] keeps them all.
+pub trait Tracer {
+    fn record(&mut self, entry: TraceEntry);
+}
+
+/// The default tracer, which compiles away to nothing.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct NoTrace;
+
+impl Tracer for NoTrace {
+    #[inline(always)]
+    fn record(&mut self, _entry: TraceEntry) {}
+}
+
+/// One instruction run by the VM.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TraceEntry {
+    /// How many instructions ran before this one
+    pub step: usize,
+    pub pc: usize,
+    pub instruction: Instruction,
+    pub acc_before: i32,
+    pub acc_after: i32,
+}
+
+/// How often the instruction at a pc ran.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct HotInstruction {
+    pub pc: usize,
+    pub instruction: Instruction,
+    pub count: usize,
+}
+
+/// A full record of a run, oldest first.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Trace {
+    pub entries: Vec<TraceEntry>,
+}
+
+impl Tracer for Trace {
+    fn record(&mut self, entry: TraceEntry) {
+        self.entries.push(entry);
+    }
+}
+
+impl Trace {
+    /// Exports the trace as CSV with a header row.
+    pub fn to_csv(&self) -> String {
+        let mut out = String::from("step,pc,instruction,acc_before,acc_after\n");
+        for e in self.entries.iter() {
+            out.push_str(&format!(
+                "{},{},{},{},{}\n",
+                e.step, e.pc, e.instruction, e.acc_before, e.acc_after
+            ));
+        }
+        out
+    }
+
+    /// Exports the trace as one JSON object per line.
+    pub fn to_json_lines(&self) -> String {
+        let mut out = String::new();
+        for e in self.entries.iter() {
+            out.push_str(&format!(
+                r#"{{"step":{},"pc":{},"instruction":"{}","acc_before":{},"acc_after":{}}}"#,
+                e.step, e.pc, e.instruction, e.acc_before, e.acc_after
+            ));
+            out.push('\n');
+        }
+        out
+    }
+
+    /// The `top` most run instructions, most runs first and then by pc.
+    pub fn hot_instructions(&self, top: usize) -> Vec<HotInstruction> {
+        let mut counts: HashMap<usize, HotInstruction> = HashMap::new();
+        for e in self.entries.iter() {
+            counts
+                .entry(e.pc)
+                .or_insert(HotInstruction {
+                    pc: e.pc,
+                    instruction: e.instruction,
+                    count: 0,
+                })
+                .count += 1;
+        }
+        let mut hot: Vec<HotInstruction> = counts.into_values().collect();
+        hot.sort_unstable_by_key(|h| (std::cmp::Reverse(h.count), h.pc));
+        hot.truncate(top);
+        hot
+    }
+
+    /// The entries from the first pc to run twice up to just before it
+    /// runs again. Without conditional jumps this is the body of the
+    /// infinite loop.
+    pub fn loop_body(&self) -> Option<&[TraceEntry]> {
+        let mut first_run = HashMap::new();
+        for (i, e) in self.entries.iter().enumerate() {
+            if let Some(&start) = first_run.get(&e.pc) {
+                return Some(&self.entries[start..i]);
+            }
+            first_run.insert(e.pc, i);
+        }
+        None
+    }
+
+    /// A short report of the `top` hot instructions and the loop body.
+    pub fn summary(&self, top: usize) -> String {
+        let mut out = format!(
+            "{} instructions run\nHot instructions:\n",
+            self.entries.len()
+        );
+        for h in self.hot_instructions(top) {
+            out.push_str(&format!(
+                "{:>8} x {:>5}: {}\n",
+                h.count, h.pc, h.instruction
+            ));
+        }
+        match self.loop_body() {
+            Some(body) => {
+                out.push_str(&format!("Loop body of {} instructions:\n", body.len()));
+                for e in body {
+                    out.push_str(&format!("{:>5}: {}\n", e.pc, e.instruction));
+                }
+            }
+            None => out.push_str("No loop\n"),
+        }
+        out
     }
 }
 
 #[derive(Debug, Clone)]
-pub struct VM {
+pub struct VM<T: Tracer = NoTrace> {
     pub pc: usize,
     pub acc: i32,
     pub program: Vec<Instruction>,
+    /// Registers a to d
+    pub registers: [i32; 4],
+    /// Instructions run so far
+    pub steps: usize,
+    /// Most instructions to run before faulting, if limited
+    pub budget: Option<usize>,
+    pub tracer: T,
+}
+
+/// What a successful [`VM::step`] left the VM doing.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StepOutcome {
+    Running,
+    /// The pc is just past the last instruction
+    Terminated,
 }
 
-impl VM {
+/// Why the VM could not run an instruction. The VM is left unchanged.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum VmFault {
+    /// A `jmp` at `pc` to outside the program, other than just past its end
+    JumpOutOfBounds { pc: usize, target: i64 },
+    /// The pc was already outside the program, e.g. after being set by hand
+    PcOutOfBounds(usize),
+    /// An `acc` at `pc` would overflow the accumulator
+    AccOverflow { pc: usize, acc: i32, val: i32 },
+    /// An `add` or `mul` at `pc` would overflow the register
+    Overflow { pc: usize, register: Register },
+    /// The instruction budget ran out after this many steps
+    BudgetExhausted(usize),
+}
+
+impl fmt::Display for VmFault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VmFault::JumpOutOfBounds { pc, target } => {
+                write!(f, "jump out of bounds at pc {} to {}", pc, target)
+            }
+            VmFault::PcOutOfBounds(pc) => write!(f, "pc {} out of bounds", pc),
+            VmFault::AccOverflow { pc, acc, val } => {
+                write!(f, "accumulator overflow at pc {}: {} + {}", pc, acc, val)
+            }
+            VmFault::Overflow { pc, register } => {
+                write!(f, "register {} overflow at pc {}", register, pc)
+            }
+            VmFault::BudgetExhausted(steps) => write!(f, "budget exhausted after {} steps", steps),
+        }
+    }
+}
+
+impl std::error::Error for VmFault {}
+
+impl VM<NoTrace> {
     pub fn new(program: Vec<Instruction>) -> Self {
+        Self::with_tracer(program, NoTrace)
+    }
+}
+
+impl<T: Tracer> VM<T> {
+    /// A VM passing every instruction it runs to `tracer`.
+    pub fn with_tracer(program: Vec<Instruction>, tracer: T) -> Self {
         Self {
             pc: 0,
             acc: 0,
             program,
+            registers: [0; 4],
+            steps: 0,
+            budget: None,
+            tracer,
+        }
+    }
+
+    pub fn register(&self, register: Register) -> i32 {
+        match register {
+            Register::Acc => self.acc,
+            Register::A => self.registers[0],
+            Register::B => self.registers[1],
+            Register::C => self.registers[2],
+            Register::D => self.registers[3],
+        }
+    }
+
+    fn register_mut(&mut self, register: Register) -> &mut i32 {
+        match register {
+            Register::Acc => &mut self.acc,
+            Register::A => &mut self.registers[0],
+            Register::B => &mut self.registers[1],
+            Register::C => &mut self.registers[2],
+            Register::D => &mut self.registers[3],
+        }
+    }
+
+    // Where a jump by `offset` lands, if it is in the program or just past its end
+    fn jump_target(&self, offset: i32) -> Result<usize, VmFault> {
+        let target = self.pc as i64 + offset as i64;
+        if (0..=self.program.len() as i64).contains(&target) {
+            Ok(target as usize)
+        } else {
+            Err(VmFault::JumpOutOfBounds {
+                pc: self.pc,
+                target,
+            })
         }
     }
-    pub fn step(&mut self) {
+
+    /// Limits the VM to running `budget` instructions in total.
+    pub fn with_budget(mut self, budget: usize) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+
+    /// Runs the instruction at the pc. Once terminated, further steps do nothing.
+    pub fn step(&mut self) -> Result<StepOutcome, VmFault> {
         use InstructionType::*;
-        let ins = &self.program[self.pc];
+        let len = self.program.len();
+        if self.pc == len {
+            return Ok(StepOutcome::Terminated);
+        }
+        let ins = *self
+            .program
+            .get(self.pc)
+            .ok_or(VmFault::PcOutOfBounds(self.pc))?;
+        if self.budget.is_some_and(|budget| self.steps >= budget) {
+            return Err(VmFault::BudgetExhausted(self.steps));
+        }
+        let (pc, acc_before) = (self.pc, self.acc);
         match ins.instruction_type {
             Acc => {
-                self.acc += ins.val;
+                self.acc = self.acc.checked_add(ins.val).ok_or(VmFault::AccOverflow {
+                    pc: self.pc,
+                    acc: self.acc,
+                    val: ins.val,
+                })?;
                 self.pc += 1;
             }
-            Jmp => {
-                self.pc = (self.pc as i32 + ins.val) as usize;
+            Jmp => self.pc = self.jump_target(ins.val)?,
+            Nop => self.pc += 1,
+            Set(r) => {
+                *self.register_mut(r) = ins.val;
+                self.pc += 1;
             }
-            Nop => {
+            Add(r) | Mul(r) => {
+                let value = if let Add(_) = ins.instruction_type {
+                    self.register(r).checked_add(ins.val)
+                } else {
+                    self.register(r).checked_mul(ins.val)
+                };
+                *self.register_mut(r) = value.ok_or(VmFault::Overflow {
+                    pc: self.pc,
+                    register: r,
+                })?;
                 self.pc += 1;
             }
+            Jz(r) | Jnz(r) => {
+                if (self.register(r) == 0) == matches!(ins.instruction_type, Jz(_)) {
+                    self.pc = self.jump_target(ins.val)?;
+                } else {
+                    self.pc += 1;
+                }
+            }
         }
+        self.tracer.record(TraceEntry {
+            step: self.steps,
+            pc,
+            instruction: ins,
+            acc_before,
+            acc_after: self.acc,
+        });
+        self.steps += 1;
+        Ok(if self.pc == len {
+            StepOutcome::Terminated
+        } else {
+            StepOutcome::Running
+        })
+    }
+
+    /// Runs to termination, returning the accumulator. Only safe on
+    /// untrusted programs with a budget, as loops otherwise never end.
+    pub fn run(&mut self) -> Result<i32, VmFault> {
+        while self.step()? == StepOutcome::Running {}
+        Ok(self.acc)
     }
 }
 
-#[aoc_generator(day8)]
-pub fn input_generator(input: &str) -> Result<Vec<Instruction>> {
-    input.lines().map(|x| Instruction::from_str(x)).collect()
+/// Where the debugger should pause, checked before an instruction runs.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Breakpoint {
+    Pc(usize),
+    /// An exact instruction type, including its register
+    Op(InstructionType),
+    /// Any instruction with this [`InstructionType::mnemonic`]
+    Opcode(&'static str),
 }
 
-#[aoc(day8, part1)]
-pub fn solve_part1(input: &[Instruction]) -> i32 {
-    let mut vm = VM::new(input.to_vec());
-    let mut seen = HashSet::new();
-    loop {
-        // println!("{:?}", vm.pc);
-        if seen.contains(&vm.pc) {
-            break vm.acc;
+/// A condition on the accumulator, checked after each instruction runs.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Watchpoint {
+    /// The accumulator changes value
+    Change,
+    /// The accumulator becomes this value
+    Equals(i32),
+}
+
+impl Watchpoint {
+    fn triggered(&self, before: i32, after: i32) -> bool {
+        match *self {
+            Watchpoint::Change => before != after,
+            Watchpoint::Equals(v) => before != v && after == v,
         }
-        seen.insert(vm.pc);
-        vm.step();
     }
 }
 
-#[aoc(day8, part2)]
-pub fn solve_part2(input: &[Instruction]) -> Result<i32> {
-    let mut vm = VM::new(input.to_vec());
-    let program_length = vm.program.len();
-    let mut seen = HashSet::new();
-    loop {
-        if vm.pc == program_length {
-            break Ok(vm.acc);
+/// Why the debugger handed control back.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Stop {
+    /// A single step finished with nothing else to report
+    Stepped,
+    /// About to run an instruction matching this breakpoint
+    Breakpoint(Breakpoint),
+    /// The last instruction moved the accumulator from `before` to `after`
+    Watchpoint {
+        watch: Watchpoint,
+        before: i32,
+        after: i32,
+    },
+    /// About to run an instruction at a pc that already ran. For programs
+    /// with conditional jumps, the registers must also match that earlier run.
+    Loop(usize),
+    /// [`Debugger::cont`] ran this many instructions without stopping
+    StepLimit(usize),
+    /// The pc is just past the last instruction
+    Terminated,
+    /// The VM could not run the next instruction
+    Fault(VmFault),
+}
+
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stop::Stepped => write!(f, "Stepped"),
+            Stop::Breakpoint(Breakpoint::Pc(pc)) => write!(f, "Breakpoint at pc {}", pc),
+            Stop::Breakpoint(Breakpoint::Op(op)) => write!(f, "Breakpoint on {}", op),
+            Stop::Breakpoint(Breakpoint::Opcode(op)) => write!(f, "Breakpoint on {}", op),
+            Stop::Watchpoint { before, after, .. } => {
+                write!(f, "Watchpoint: acc {} -> {}", before, after)
+            }
+            Stop::Loop(pc) => write!(f, "Loop: pc {} already ran", pc),
+            Stop::StepLimit(steps) => write!(f, "Step limit: ran {} instructions", steps),
+            Stop::Terminated => write!(f, "Terminated"),
+            Stop::Fault(fault) => write!(f, "Fault: {}", fault),
         }
-        if seen.contains(&vm.pc) {
-            break Err(anyhow!("Infinite loop, pc: {}, acc: {}", vm.pc, vm.acc));
+    }
+}
+
+/// How many instructions [`Debugger::cont`] runs before giving up, by default.
+pub const DEFAULT_STEP_LIMIT: usize = 1_000_000;
+
+/// Wraps a [`VM`] with breakpoints, accumulator watchpoints, loop detection
+/// and a record of every pc run so far.
+#[derive(Debug, Clone)]
+pub struct Debugger {
+    pub vm: VM,
+    pub breakpoints: Vec<Breakpoint>,
+    pub watchpoints: Vec<Watchpoint>,
+    /// Most instructions one [`Debugger::cont`] may run, if limited
+    pub step_limit: Option<usize>,
+    history: Vec<usize>,
+    seen: Vec<bool>,
+    /// (pc, acc, registers) before each instruction run, for programs whose
+    /// control flow depends on the registers
+    states: HashSet<(usize, i32, [i32; 4])>,
+    pc_loops: bool,
+}
+
+impl Debugger {
+    pub fn new(vm: VM) -> Self {
+        let seen = vec![false; vm.program.len()];
+        // Revisiting a pc only proves a loop when control flow ignores the registers
+        let pc_loops = !vm
+            .program
+            .iter()
+            .any(|ins| ins.instruction_type.is_conditional());
+        Self {
+            vm,
+            breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
+            step_limit: Some(DEFAULT_STEP_LIMIT),
+            history: Vec::new(),
+            seen,
+            states: HashSet::new(),
+            pc_loops,
         }
-        use InstructionType::*;
-        match vm.program[vm.pc].instruction_type {
-            Acc => {}
-            Nop => {
-                let mut newvm = vm.clone();
-                newvm.program[newvm.pc].instruction_type = Jmp;
-                let out = run_vm(newvm, seen.clone());
-                if let Ok(newacc) = out {
-                    break Ok(newacc);
+    }
+
+    /// Puts the VM back at the start, keeping breakpoints and watchpoints.
+    pub fn reset(&mut self) {
+        self.vm.pc = 0;
+        self.vm.acc = 0;
+        self.vm.registers = [0; 4];
+        self.vm.steps = 0;
+        self.history.clear();
+        self.seen.iter_mut().for_each(|s| *s = false);
+        self.states.clear();
+    }
+
+    /// Every pc run so far, oldest first.
+    pub fn history(&self) -> &[usize] {
+        &self.history
+    }
+
+    /// The last `n` pcs run, most recent first.
+    pub fn backtrace(&self, n: usize) -> Vec<usize> {
+        self.history.iter().rev().take(n).copied().collect()
+    }
+
+    fn terminated(&self) -> bool {
+        self.vm.pc == self.vm.program.len()
+    }
+
+    fn breakpoint(&self) -> Option<Breakpoint> {
+        let op = self.vm.program.get(self.vm.pc)?.instruction_type;
+        self.breakpoints.iter().copied().find(|b| match *b {
+            Breakpoint::Pc(pc) => pc == self.vm.pc,
+            Breakpoint::Op(o) => o == op,
+            Breakpoint::Opcode(mnemonic) => mnemonic == op.mnemonic(),
+        })
+    }
+
+    fn state(&self) -> (usize, i32, [i32; 4]) {
+        (self.vm.pc, self.vm.acc, self.vm.registers)
+    }
+
+    fn looped(&self) -> bool {
+        if self.pc_loops {
+            self.seen.get(self.vm.pc) == Some(&true)
+        } else {
+            self.states.contains(&self.state())
+        }
+    }
+
+    /// Runs one instruction, ignoring breakpoints and loops.
+    pub fn step(&mut self) -> Stop {
+        if self.terminated() {
+            return Stop::Terminated;
+        }
+        let pc = self.vm.pc;
+        let before = self.vm.acc;
+        let state = self.state();
+        let outcome = match self.vm.step() {
+            Ok(outcome) => outcome,
+            Err(fault) => return Stop::Fault(fault),
+        };
+        if !self.pc_loops {
+            self.states.insert(state);
+        }
+        self.seen[pc] = true;
+        self.history.push(pc);
+        let after = self.vm.acc;
+        match self.watchpoints.iter().find(|w| w.triggered(before, after)) {
+            Some(&watch) => Stop::Watchpoint {
+                watch,
+                before,
+                after,
+            },
+            None if outcome == StepOutcome::Terminated => Stop::Terminated,
+            None => Stop::Stepped,
+        }
+    }
+
+    /// Runs until a breakpoint, watchpoint, loop, the step limit or the end
+    /// of the program. Always runs at least one instruction, so continuing
+    /// from a breakpoint moves past it.
+    pub fn cont(&mut self) -> Stop {
+        let mut steps = 0;
+        loop {
+            if self.terminated() {
+                return Stop::Terminated;
+            }
+            if self.looped() {
+                return Stop::Loop(self.vm.pc);
+            }
+            if steps > 0 {
+                if let Some(b) = self.breakpoint() {
+                    return Stop::Breakpoint(b);
                 }
             }
-            Jmp => {
-                let mut newvm = vm.clone();
-                newvm.program[newvm.pc].instruction_type = Nop;
-                let out = run_vm(newvm, seen.clone());
-                if let Ok(newacc) = out {
-                    break Ok(newacc);
+            if self.step_limit.is_some_and(|limit| steps >= limit) {
+                return Stop::StepLimit(steps);
+            }
+            steps += 1;
+            match self.step() {
+                Stop::Stepped => {}
+                stop => return stop,
+            }
+        }
+    }
+}
+
+const MNEMONICS: [&str; 8] = ["nop", "acc", "jmp", "set", "add", "mul", "jz", "jnz"];
+
+const REPL_HELP: &str = "Commands:
+  s, step [n]         run n instructions (default 1)
+  c, continue         run until something stops the VM
+  b, break <pc|op>    break at a pc, or on an opcode such as jmp or jz
+  b, break <op> <reg> break on an opcode using a register, e.g. jz a
+  limit <n|off>       stop continuing after n instructions
+  w, watch [value]    stop when acc changes, or becomes value
+  d, delete           remove all breakpoints and watchpoints
+  bt [n]              show the last n pcs run (default 10)
+  p, print            show pc, acc and the next instruction
+  r, reset            restart the program
+  q, quit             leave the debugger
+";
+
+/// A line-oriented command interface to `debugger`, reading commands until
+/// `quit` or the end of `input`. Pass `io::stdin().lock()` and
+/// `io::stdout()` to use it interactively.
+pub fn repl<R: BufRead, W: Write>(debugger: &mut Debugger, input: R, mut out: W) -> io::Result<()> {
+    for line in input.lines() {
+        let line = line?;
+        let mut words = line.split_whitespace();
+        let (command, arg) = match words.next() {
+            Some(command) => (command, words.next()),
+            None => continue,
+        };
+        let rest: Vec<&str> = words.collect();
+        match (command, arg) {
+            ("s", n) | ("step", n) => match n.map_or(Ok(1), str::parse::<usize>) {
+                Ok(n) => {
+                    let mut stop = Stop::Stepped;
+                    for _ in 0..n {
+                        stop = debugger.step();
+                        if stop != Stop::Stepped {
+                            break;
+                        }
+                    }
+                    writeln!(out, "{}", stop)?;
+                    print_state(debugger, &mut out)?;
                 }
+                Err(_) => writeln!(out, "Bad step count: {}", n.unwrap_or(""))?,
+            },
+            ("c", None) | ("continue", None) => {
+                let stop = debugger.cont();
+                writeln!(out, "{}", stop)?;
+                print_state(debugger, &mut out)?;
             }
+            ("b", Some(target)) | ("break", Some(target)) => {
+                let breakpoint = match (target.parse(), rest.as_slice()) {
+                    (Ok(pc), []) => Some(Breakpoint::Pc(pc)),
+                    (_, []) => MNEMONICS
+                        .iter()
+                        .find(|&&m| m == target)
+                        .map(|&m| Breakpoint::Opcode(m)),
+                    (_, [register]) => format!("{} {}", target, register)
+                        .parse()
+                        .ok()
+                        .map(Breakpoint::Op),
+                    _ => None,
+                };
+                match breakpoint {
+                    Some(b) => {
+                        debugger.breakpoints.push(b);
+                        writeln!(out, "Breakpoint {} set", debugger.breakpoints.len())?;
+                    }
+                    None => writeln!(
+                        out,
+                        "Bad breakpoint: {}",
+                        [&[target], &rest[..]].concat().join(" ")
+                    )?,
+                }
+            }
+            ("w", value) | ("watch", value) => match value.map(str::parse) {
+                None => debugger.watchpoints.push(Watchpoint::Change),
+                Some(Ok(v)) => debugger.watchpoints.push(Watchpoint::Equals(v)),
+                Some(Err(_)) => writeln!(out, "Bad watch value: {}", value.unwrap_or(""))?,
+            },
+            ("d", None) | ("delete", None) => {
+                debugger.breakpoints.clear();
+                debugger.watchpoints.clear();
+            }
+            ("bt", n) => match n.map_or(Ok(10), str::parse::<usize>) {
+                Ok(n) => {
+                    for pc in debugger.backtrace(n) {
+                        writeln!(out, "{:>5}: {}", pc, debugger.vm.program[pc])?;
+                    }
+                }
+                Err(_) => writeln!(out, "Bad count: {}", n.unwrap_or(""))?,
+            },
+            ("limit", Some(n)) => match n.parse() {
+                Ok(n) => debugger.step_limit = Some(n),
+                Err(_) if n == "off" => debugger.step_limit = None,
+                Err(_) => writeln!(out, "Bad limit: {}", n)?,
+            },
+            ("p", None) | ("print", None) => print_state(debugger, &mut out)?,
+            ("r", None) | ("reset", None) => debugger.reset(),
+            ("q", None) | ("quit", None) => break,
+            _ => write!(out, "Unknown command: {}\n{}", line.trim(), REPL_HELP)?,
         }
+    }
+    Ok(())
+}
+
+fn print_state<W: Write>(debugger: &Debugger, out: &mut W) -> io::Result<()> {
+    let vm = &debugger.vm;
+    match vm.program.get(vm.pc) {
+        Some(ins) => writeln!(out, "pc: {} acc: {} next: {}", vm.pc, vm.acc, ins),
+        None => writeln!(out, "pc: {} acc: {}", vm.pc, vm.acc),
+    }
+}
+
+#[aoc_generator(day8)]
+pub fn input_generator(input: &str) -> Result<Vec<Instruction>> {
+    Ok(assemble(input)?)
+}
+
+#[aoc(day8, part1)]
+pub fn solve_part1(input: &[Instruction]) -> Result<i32> {
+    let mut debugger = Debugger::new(VM::new(input.to_vec()));
+    match debugger.cont() {
+        Stop::Loop(_) => Ok(debugger.vm.acc),
+        stop => Err(anyhow!("Program did not loop: {}", stop)),
+    }
+}
 
-        seen.insert(vm.pc);
-        vm.step();
+/// The single instruction flip that makes a program terminate.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Repair {
+    pub index: usize,
+    pub original: InstructionType,
+    pub patched: InstructionType,
+    /// The accumulator once the patched program terminates
+    pub acc: i32,
+}
+
+// The pcs that can follow `ins` at `pc`, falling through and jumping,
+// when they are in the program or just past its end
+fn successors(ins: &Instruction, pc: usize, len: usize) -> [Option<usize>; 2] {
+    let jump = pc as i64 + ins.val as i64;
+    let jump = if (0..=len as i64).contains(&jump) {
+        Some(jump as usize)
+    } else {
+        None
+    };
+    match ins.instruction_type {
+        InstructionType::Jmp => [None, jump],
+        InstructionType::Jz(_) | InstructionType::Jnz(_) => [Some(pc + 1), jump],
+        _ => [Some(pc + 1), None],
     }
 }
 
-// Accumulator never used itself in jmp, so if we ever see previous instruction
-// Then we have an infinite loop
-fn run_vm(mut vm: VM, mut seen: HashSet<usize>) -> Result<i32> {
-    let program_length = vm.program.len();
-    loop {
-        if vm.pc == program_length {
-            break Ok(vm.acc);
+/// For each pc up to and including `program.len()`, whether running from
+/// there reaches the end of the program. Found by a breadth first search
+/// back from the end over the reversed control flow graph. Conditional
+/// jumps count as able to go either way.
+pub fn terminating_pcs(program: &[Instruction]) -> Vec<bool> {
+    let len = program.len();
+    let mut preds = vec![Vec::new(); len + 1];
+    for (pc, ins) in program.iter().enumerate() {
+        for next in successors(ins, pc, len).iter().flatten() {
+            preds[*next].push(pc);
+        }
+    }
+    let mut terminates = vec![false; len + 1];
+    terminates[len] = true;
+    let mut queue = VecDeque::from(vec![len]);
+    while let Some(pc) = queue.pop_front() {
+        for &pred in preds[pc].iter() {
+            if !terminates[pred] {
+                terminates[pred] = true;
+                queue.push_back(pred);
+            }
         }
-        // println!("{:?}", vm.pc);
-        if seen.contains(&vm.pc) {
-            break Err(anyhow!("Infinite loop, pc: {}, acc: {}", vm.pc, vm.acc));
+    }
+    terminates
+}
+
+/// Finds the one `nop`/`jmp` swap that makes the program terminate, in time
+/// linear in the program length.
+///
+/// Only instructions reached from pc 0 before the first repeat can matter,
+/// and swapping one of them fixes the program exactly when its new
+/// successor already terminates, as nothing else on the path changes. That
+/// only holds without conditional jumps, so programs using them are rejected.
+pub fn repair(program: &[Instruction]) -> Result<Repair> {
+    if let Some(pc) = program
+        .iter()
+        .position(|ins| ins.instruction_type.is_conditional())
+    {
+        return Err(anyhow!("Cannot repair conditional jump at pc {}", pc));
+    }
+    let len = program.len();
+    let terminates = terminating_pcs(program);
+    if terminates[0] {
+        return Err(anyhow!("Program already terminates"));
+    }
+    let mut seen = vec![false; len];
+    let mut pc = 0;
+    while pc < len && !seen[pc] {
+        seen[pc] = true;
+        let ins = program[pc];
+        let patched = match ins.instruction_type {
+            InstructionType::Nop => Some(InstructionType::Jmp),
+            InstructionType::Jmp => Some(InstructionType::Nop),
+            _ => None,
+        };
+        if let Some(patched) = patched {
+            let flipped = Instruction {
+                instruction_type: patched,
+                ..ins
+            };
+            let next = successors(&flipped, pc, len)
+                .iter()
+                .flatten()
+                .next()
+                .copied();
+            if next.is_some_and(|next| terminates[next]) {
+                let mut fixed = program.to_vec();
+                fixed[pc] = flipped;
+                let mut debugger = Debugger::new(VM::new(fixed));
+                return match debugger.cont() {
+                    Stop::Terminated => Ok(Repair {
+                        index: pc,
+                        original: ins.instruction_type,
+                        patched,
+                        acc: debugger.vm.acc,
+                    }),
+                    stop => Err(anyhow!("Patched program did not terminate: {}", stop)),
+                };
+            }
         }
-        seen.insert(vm.pc);
-        vm.step();
+        pc = match successors(&ins, pc, len).iter().flatten().next() {
+            Some(&next) => next,
+            None => return Err(anyhow!("Jump out of bounds at pc {}", pc)),
+        };
     }
+    Err(anyhow!("No single swap fixes the loop at pc {}", pc))
+}
+
+#[aoc(day8, part2)]
+pub fn solve_part2(input: &[Instruction]) -> Result<i32> {
+    repair(input).map(|r| r.acc)
 }
 
 #[cfg(test)]
@@ -170,7 +1115,7 @@ acc +1
 jmp -4
 acc +6";
         let input = input_generator(ex)?;
-        assert_eq!(solve_part1(&input), 5);
+        assert_eq!(solve_part1(&input)?, 5);
         Ok(())
     }
     #[test]
@@ -188,4 +1133,331 @@ acc +6";
         assert_eq!(solve_part2(&input)?, 8);
         Ok(())
     }
+    #[test]
+    fn test_debugger() -> Result<()> {
+        let ex = "nop +0
+acc +1
+jmp +4
+acc +3
+jmp -3
+acc -99
+acc +1
+jmp -4
+acc +6";
+        let mut debugger = Debugger::new(VM::new(input_generator(ex)?));
+        debugger.breakpoints.push(Breakpoint::Pc(6));
+        assert_eq!(debugger.cont(), Stop::Breakpoint(Breakpoint::Pc(6)));
+        assert_eq!(debugger.history(), &[0, 1, 2]);
+
+        debugger.watchpoints.push(Watchpoint::Equals(2));
+        assert_eq!(
+            debugger.cont(),
+            Stop::Watchpoint {
+                watch: Watchpoint::Equals(2),
+                before: 1,
+                after: 2
+            }
+        );
+        debugger
+            .breakpoints
+            .push(Breakpoint::Op(InstructionType::Acc));
+        assert_eq!(
+            debugger.cont(),
+            Stop::Breakpoint(Breakpoint::Op(InstructionType::Acc))
+        );
+        assert_eq!(debugger.vm.pc, 3);
+        assert_eq!(debugger.cont(), Stop::Loop(1));
+        assert_eq!(debugger.vm.acc, 5);
+        assert_eq!(debugger.backtrace(3), vec![4, 3, 7]);
+        Ok(())
+    }
+    #[test]
+    fn test_repl() -> Result<()> {
+        let ex = "nop +0
+acc +1
+jmp +4
+acc +3
+jmp -3
+acc -99
+acc +1
+jmp -4
+acc +6";
+        let mut debugger = Debugger::new(VM::new(input_generator(ex)?));
+        let commands = "break jmp\nc\nstep 2\n\nbt 2\nwatch\ncontinue\nfrobnicate\nq\np\n";
+        let mut out = Vec::new();
+        repl(&mut debugger, commands.as_bytes(), &mut out)?;
+        let out = String::from_utf8(out)?;
+        let expected = "Breakpoint 1 set
+Breakpoint on jmp
+pc: 2 acc: 1 next: jmp +4
+Stepped
+pc: 7 acc: 2 next: jmp -4
+    6: acc +1
+    2: jmp +4
+Watchpoint: acc 2 -> 5
+pc: 4 acc: 5 next: jmp -3
+Unknown command: frobnicate
+";
+        assert_eq!(&out[..expected.len()], expected);
+        assert!(out.ends_with(REPL_HELP));
+        Ok(())
+    }
+    #[test]
+    fn test_repair() -> Result<()> {
+        let ex = "nop +0
+acc +1
+jmp +4
+acc +3
+jmp -3
+acc -99
+acc +1
+jmp -4
+acc +6";
+        let input = input_generator(ex)?;
+        assert_eq!(
+            terminating_pcs(&input),
+            vec![false, false, false, false, false, false, false, false, true, true]
+        );
+        assert_eq!(
+            repair(&input)?,
+            Repair {
+                index: 7,
+                original: InstructionType::Jmp,
+                patched: InstructionType::Nop,
+                acc: 8
+            }
+        );
+
+        // Flipping the nop at 0 jumps straight past the end
+        let input = input_generator("nop +3\nacc +2\njmp -2")?;
+        assert_eq!(repair(&input)?.index, 0);
+        assert_eq!(repair(&input)?.acc, 0);
+        assert!(repair(&input_generator("acc +1\nnop +0")?).is_err());
+        // Flipping the nop would also terminate, but nothing needs repairing
+        let fine = repair(&input_generator("nop +1\nacc +1")?);
+        assert_eq!(
+            fine.map_err(|e| e.to_string()),
+            Err("Program already terminates".to_string())
+        );
+        assert!(repair(&input_generator("jmp +0\njmp -1")?).is_err());
+        Ok(())
+    }
+    #[test]
+    fn test_faults() -> Result<()> {
+        let mut vm = VM::new(input_generator("nop +0\njmp -2")?);
+        assert_eq!(vm.step(), Ok(StepOutcome::Running));
+        assert_eq!(
+            vm.step(),
+            Err(VmFault::JumpOutOfBounds { pc: 1, target: -1 })
+        );
+        assert_eq!((vm.pc, vm.steps), (1, 1));
+
+        let mut vm = VM::new(input_generator("jmp +3\nacc +1")?);
+        assert_eq!(vm.run(), Err(VmFault::JumpOutOfBounds { pc: 0, target: 3 }));
+        let mut vm = VM::new(input_generator("jmp +2\nacc +1")?);
+        assert_eq!(vm.run(), Ok(0));
+        assert_eq!(vm.step(), Ok(StepOutcome::Terminated));
+
+        let mut vm = VM::new(input_generator("acc +2147483647\nacc +1")?);
+        assert_eq!(
+            vm.run(),
+            Err(VmFault::AccOverflow {
+                pc: 1,
+                acc: i32::MAX,
+                val: 1
+            })
+        );
+
+        let mut vm = VM::new(input_generator("acc +1\njmp -1")?).with_budget(5);
+        assert_eq!(vm.run(), Err(VmFault::BudgetExhausted(5)));
+        assert_eq!(vm.acc, 3);
+
+        vm.pc = 7;
+        let mut debugger = Debugger::new(vm);
+        assert_eq!(debugger.cont(), Stop::Fault(VmFault::PcOutOfBounds(7)));
+        Ok(())
+    }
+    #[test]
+    fn test_assembler() -> Result<()> {
+        let ex = "; acc = 2 * (3 * 4) by repeated addition
+    set a 4
+loop: acc +3    ; runs 4 times
+    add a -1
+    jnz a loop
+    mul acc 2
+    jz b end
+    acc +1000
+end:";
+        let program = assemble(ex)?;
+        assert_eq!(program.len(), 7);
+        assert_eq!(program[3].to_string(), "jnz a -2");
+        assert_eq!(program[5].to_string(), "jz b +2");
+        assert_eq!(VM::new(program.clone()).run(), Ok(24));
+
+        let listing = disassemble(&program);
+        assert_eq!(
+            listing,
+            "    set a +4
+L1:
+    acc +3
+    add a -1
+    jnz a L1
+    mul acc +2
+    jz b L7
+    acc +1000
+L7:
+"
+        );
+        assert_eq!(assemble(&listing)?, program);
+
+        // Loop detection would be wrong here, as pc 1 legitimately runs 4 times
+        let mut debugger = Debugger::new(VM::new(program));
+        assert_eq!(debugger.cont(), Stop::Terminated);
+        assert!(repair(&debugger.vm.program).is_err());
+        Ok(())
+    }
+    #[test]
+    fn test_assembler_subset() -> Result<()> {
+        let ex = "nop +0
+acc +1
+jmp +4
+acc +3
+jmp -3
+acc -99
+acc +1
+jmp -4
+acc +6";
+        let program = input_generator(ex)?;
+        let listing = disassemble(&program);
+        assert!(listing.starts_with("    nop +0\nL1:\n    acc +1\n    jmp L6\n"));
+        assert_eq!(assemble(&listing)?, program);
+        assert_eq!(
+            "jz c".parse::<InstructionType>()?,
+            InstructionType::Jz(Register::C)
+        );
+        Ok(())
+    }
+    #[test]
+    fn test_assembler_errors() {
+        let error = |ex: &str| assemble(ex).expect_err("Should not assemble");
+        assert_eq!(
+            error("nop +0\n\njmp nowhere"),
+            AsmError {
+                line: 3,
+                syntax: AsmSyntax::UnknownLabel("nowhere".to_string())
+            }
+        );
+        assert_eq!(
+            error("x: nop +0\nx: nop +0").syntax,
+            AsmSyntax::DuplicateLabel("x".to_string())
+        );
+        assert_eq!(
+            error("set e 1").syntax,
+            AsmSyntax::BadRegister("e".to_string())
+        );
+        assert_eq!(
+            error("x: set a x").syntax,
+            AsmSyntax::BadOperand("x".to_string())
+        );
+        assert_eq!(error("acc").syntax, AsmSyntax::MissingOperand);
+        assert_eq!(
+            error("acc +1 +2").to_string(),
+            "Line 1: unexpected operand \"+2\""
+        );
+    }
+    #[test]
+    fn test_trace() -> Result<()> {
+        let ex = "nop +0
+acc +1
+jmp +4
+acc +3
+jmp -3
+acc -99
+acc +1
+jmp -4
+acc +6";
+        let mut vm = VM::with_tracer(input_generator(ex)?, Trace::default()).with_budget(20);
+        assert_eq!(vm.run(), Err(VmFault::BudgetExhausted(20)));
+        let trace = vm.tracer;
+        assert_eq!(trace.entries.len(), 20);
+
+        let body: Vec<usize> = trace
+            .loop_body()
+            .unwrap_or(&[])
+            .iter()
+            .map(|e| e.pc)
+            .collect();
+        assert_eq!(body, vec![1, 2, 6, 7, 3, 4]);
+        let hot = trace.hot_instructions(2);
+        assert_eq!(
+            hot.iter().map(|h| (h.pc, h.count)).collect::<Vec<_>>(),
+            vec![(1, 4), (2, 3)]
+        );
+        assert!(trace.summary(1).starts_with(
+            "20 instructions run
+Hot instructions:
+       4 x     1: acc +1
+Loop body of 6 instructions:
+    1: acc +1
+    2: jmp +4
+"
+        ));
+
+        let csv = trace.to_csv();
+        assert!(csv.starts_with(
+            "step,pc,instruction,acc_before,acc_after\n0,0,nop +0,0,0\n1,1,acc +1,0,1\n"
+        ));
+        assert_eq!(csv.lines().count(), 21);
+        assert_eq!(
+            trace.to_json_lines().lines().nth(4),
+            Some(r#"{"step":4,"pc":7,"instruction":"jmp -4","acc_before":2,"acc_after":2}"#)
+        );
+
+        let mut vm = VM::with_tracer(input_generator("acc +1\nacc +2")?, Trace::default());
+        assert_eq!(vm.run(), Ok(3));
+        assert_eq!(vm.tracer.loop_body(), None);
+        assert!(vm.tracer.summary(5).ends_with("No loop\n"));
+        Ok(())
+    }
+    #[test]
+    fn test_debugger_conditional() -> Result<()> {
+        // Same state every time round, so this is caught as a loop
+        let mut debugger = Debugger::new(VM::new(assemble("set a 1\nl: jnz a l")?));
+        assert_eq!(debugger.cont(), Stop::Loop(1));
+
+        // Counts down, so revisiting pc 1 is not a loop
+        let mut debugger =
+            Debugger::new(VM::new(assemble("set a 3\nl: add a -1\njnz a l\nacc +1")?));
+        assert_eq!(debugger.cont(), Stop::Terminated);
+        assert_eq!(debugger.history(), &[0, 1, 2, 1, 2, 1, 2, 3]);
+
+        // Never repeats a state, so only the step limit stops it
+        let mut debugger = Debugger::new(VM::new(assemble("l: add a 1\njnz a l")?));
+        debugger.step_limit = Some(100);
+        assert_eq!(debugger.cont(), Stop::StepLimit(100));
+        assert_eq!(debugger.vm.register(Register::A), 50);
+
+        let commands = "b jz\nb jnz a\nb mul d\nb mul x\nb frob\nlimit 10\nc\nc\nlimit off\nc\n";
+        let mut debugger = Debugger::new(VM::new(assemble(
+            "set a 2\njz a +2\nl: add a -1\njnz a l\nmul d 3",
+        )?));
+        let mut out = Vec::new();
+        repl(&mut debugger, commands.as_bytes(), &mut out)?;
+        assert_eq!(
+            String::from_utf8(out)?,
+            "Breakpoint 1 set
+Breakpoint 2 set
+Breakpoint 3 set
+Bad breakpoint: mul x
+Bad breakpoint: frob
+Breakpoint on jz
+pc: 1 acc: 0 next: jz a +2
+Breakpoint on jnz a
+pc: 3 acc: 0 next: jnz a -1
+Breakpoint on jnz a
+pc: 3 acc: 0 next: jnz a -1
+"
+        );
+        Ok(())
+    }
 }